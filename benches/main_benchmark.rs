@@ -1,5 +1,6 @@
-use acme_disk_use::DiskUse;
+use acme_disk_use::{DirStat, DiskUse, ScanOptions};
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use tempfile::TempDir;
@@ -132,7 +133,11 @@ fn benchmark_cold_cache(c: &mut Criterion) {
                 |(temp_dir, test_dir)| {
                     // Benchmark: Cold cache scan
                     let mut disk_use = DiskUse::new(PathBuf::from("/tmp/bench_cache.bin"));
-                    let stats = disk_use.scan_with_options(&test_dir, true).unwrap();
+                    let options = ScanOptions {
+                        ignore_cache: true,
+                        ..Default::default()
+                    };
+                    let stats = disk_use.scan_with_options(&test_dir, &options).unwrap();
                     black_box(stats);
                     drop(temp_dir); // Clean up
                 },
@@ -170,7 +175,7 @@ fn benchmark_warm_cache(c: &mut Criterion) {
                     let mut disk_use = DiskUse::new(cache_path.clone());
 
                     // Initial scan to populate cache
-                    disk_use.scan_with_options(&test_dir, false).unwrap();
+                    disk_use.scan(&test_dir).unwrap();
                     disk_use.save_cache().unwrap();
 
                     (temp_dir, test_dir, cache_path)
@@ -178,7 +183,7 @@ fn benchmark_warm_cache(c: &mut Criterion) {
                 |(temp_dir, test_dir, cache_path)| {
                     // Benchmark: Warm cache scan (should use cache)
                     let mut disk_use = DiskUse::new(cache_path);
-                    let stats = disk_use.scan_with_options(&test_dir, false).unwrap();
+                    let stats = disk_use.scan(&test_dir).unwrap();
                     black_box(stats);
                     drop(temp_dir); // Clean up
                 },
@@ -219,7 +224,7 @@ fn benchmark_cache_invalidation(c: &mut Criterion) {
                         let mut disk_use = DiskUse::new(cache_path.clone());
 
                         // Initial scan
-                        disk_use.scan_with_options(&test_dir, false).unwrap();
+                        disk_use.scan(&test_dir).unwrap();
                         disk_use.save_cache().unwrap();
 
                         // Add a new file to trigger cache invalidation
@@ -231,7 +236,7 @@ fn benchmark_cache_invalidation(c: &mut Criterion) {
                     |(temp_dir, test_dir, cache_path)| {
                         // Benchmark: Scan with cache invalidation
                         let mut disk_use = DiskUse::new(cache_path);
-                        let stats = disk_use.scan_with_options(&test_dir, false).unwrap();
+                        let stats = disk_use.scan(&test_dir).unwrap();
                         black_box(stats);
                         drop(temp_dir); // Clean up
                     },
@@ -244,6 +249,84 @@ fn benchmark_cache_invalidation(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark opening a saved cache and querying it (startup time)
+fn benchmark_cache_startup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cache_startup");
+
+    for roots in [10, 100, 500] {
+        // Setup once: scan many small roots into one cache file
+        let temp_dir = TempDir::new().unwrap();
+        let cache_path = temp_dir.path().join("cache.bin");
+        let mut disk_use = DiskUse::new(cache_path.clone());
+        let root_dirs: Vec<PathBuf> = (0..roots)
+            .map(|i| {
+                let root = temp_dir.path().join(format!("root_{}", i));
+                fs::create_dir(&root).unwrap();
+                create_test_structure(&root, 2, 3, 2, 512).unwrap();
+                disk_use.scan(&root).unwrap();
+                root
+            })
+            .collect();
+        disk_use.save_cache().unwrap();
+
+        // Baseline: the same roots in one bincode blob, as the cache was stored before
+        let full_path = temp_dir.path().join("full.bin");
+        let full: HashMap<PathBuf, DirStat> = root_dirs
+            .iter()
+            .map(|dir| {
+                let stats = disk_use.get_stats(dir).unwrap().clone();
+                (stats.path().to_path_buf(), stats)
+            })
+            .collect();
+        fs::write(&full_path, bincode::serialize(&full).unwrap()).unwrap();
+        drop(disk_use);
+
+        group.bench_with_input(
+            BenchmarkId::new("deserialize_whole_cache", roots),
+            &root_dirs,
+            |b, dirs| {
+                let subdir = dirs[0].canonicalize().unwrap().join("subdir_1");
+                b.iter(|| {
+                    let bytes = fs::read(&full_path).unwrap();
+                    let full: HashMap<PathBuf, DirStat> = bincode::deserialize(&bytes).unwrap();
+                    let stats = full.values().find_map(|root| root.find(&subdir)).unwrap();
+                    black_box(stats.total_size())
+                })
+            },
+        );
+
+        // Only the queried root is read from the cache file
+        group.bench_with_input(
+            BenchmarkId::new("query_one_root", roots),
+            &root_dirs,
+            |b, dirs| {
+                b.iter(|| {
+                    let disk_use = DiskUse::new(cache_path.clone());
+                    let stats = disk_use.get_stats(dirs[0].join("subdir_1")).unwrap();
+                    black_box(stats.total_size())
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("query_all_roots", roots),
+            &root_dirs,
+            |b, dirs| {
+                b.iter(|| {
+                    let disk_use = DiskUse::new(cache_path.clone());
+                    let total: u64 = dirs
+                        .iter()
+                        .map(|dir| disk_use.get_stats(dir).unwrap().total_size())
+                        .sum();
+                    black_box(total)
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
 /// Benchmark format_size function
 fn benchmark_format_size(c: &mut Criterion) {
     use acme_disk_use::format_size;
@@ -275,6 +358,7 @@ criterion_group!(
     benchmark_cold_cache,
     benchmark_warm_cache,
     benchmark_cache_invalidation,
+    benchmark_cache_startup,
     benchmark_format_size
 );
 criterion_main!(benches);
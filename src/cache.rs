@@ -1,22 +1,161 @@
 //! Cache management module for storing and retrieving disk usage statistics
 
+use fs4::FileExt;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs, io,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Read},
     path::{Path, PathBuf},
+    sync::OnceLock,
+    time::SystemTime,
 };
 
+use crate::cache_archive::{ArchiveWriter, CacheArchive};
+use crate::cache_format;
+use crate::error::{Error, Result};
 use crate::scanner::DirStat;
 
+/// Key of a cached root: its canonical path and the filter fingerprint it was scanned with
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct RootKey {
+    pub(crate) path: PathBuf,
+    pub(crate) fingerprint: u64,
+}
+
 /// Cache structure for storing multiple directory scan results
-#[derive(Serialize, Deserialize, Debug, Default)]
+///
+/// Roots loaded from a cache file in the current format stay in its mapped
+/// archive until they are changed. `roots` holds roots read from older
+/// formats and every root changed since loading, and takes precedence.
+#[derive(Debug, Default)]
 pub(crate) struct Cache {
-    pub(crate) roots: HashMap<PathBuf, DirStat>,
-    pub(crate) version: u32,
+    pub(crate) roots: HashMap<RootKey, DirStat>,
+    pub(crate) archive: Option<CacheArchive>,
+    /// Roots deliberately removed since the last save, hidden in the archive
+    /// and not merged back in from the file
+    pub(crate) removed: HashSet<RootKey>,
+    /// Cache file whose backup holds the previous generation of the archive
+    pub(crate) cache_path: Option<PathBuf>,
+    /// Archive of the previous generation, opened the first time a root is
+    /// found damaged in `archive`
+    pub(crate) backup: OnceLock<Option<CacheArchive>>,
+}
+
+impl Cache {
+    /// Whether a root is cached
+    fn contains(&self, key: &RootKey) -> bool {
+        self.roots.contains_key(key) || self.archived(key).is_some()
+    }
+
+    /// The mapped archive, if a root is still served from it
+    ///
+    /// A root whose archive is damaged is served from the backup, if it is intact there.
+    fn archived(&self, key: &RootKey) -> Option<&CacheArchive> {
+        if self.roots.contains_key(key) || self.removed.contains(key) {
+            return None;
+        }
+        let archive = self.archive.as_ref()?;
+        if archive.contains(key) {
+            Some(archive)
+        } else if archive.lists(key) {
+            self.backup().filter(|backup| backup.contains(key))
+        } else {
+            None
+        }
+    }
+
+    /// The archive of the previous generation, mapped the first time it is needed
+    fn backup(&self) -> Option<&CacheArchive> {
+        self.backup.get_or_init(|| self.open_backup(true)).as_ref()
+    }
+
+    /// Map the archive of the previous generation, taking a shared lock if asked
+    ///
+    /// A process holding the exclusive lock must not ask, as a second lock
+    /// on the same file would wait for it forever.
+    fn open_backup(&self, lock: bool) -> Option<CacheArchive> {
+        let cache_path = self.cache_path.as_deref()?;
+        let _lock = lock.then(|| CacheManager::lock(cache_path, false).ok());
+        let backup_path = CacheManager::backup_path(cache_path);
+        match CacheManager::load_from_file(&backup_path) {
+            Ok(backup) => backup.archive,
+            Err(err) => {
+                log::warn!("cannot fall back to the backup: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Keys of all cached roots
+    fn keys(&self) -> impl Iterator<Item = &RootKey> {
+        let archived = self
+            .archive
+            .iter()
+            .flat_map(CacheArchive::keys)
+            .filter(|key| !self.roots.contains_key(*key) && !self.removed.contains(*key));
+        self.roots.keys().chain(archived)
+    }
+
+    /// A cached root, deserialized if it is archived
+    fn root(&self, key: &RootKey) -> Option<Cow<'_, DirStat>> {
+        match self.roots.get(key) {
+            Some(stat) => Some(Cow::Borrowed(stat)),
+            None => self.archived(key)?.root(key).map(Cow::Owned),
+        }
+    }
+
+    /// A cached root to borrow, deserialized as a whole the first time if archived
+    fn root_ref(&self, key: &RootKey) -> Option<&DirStat> {
+        match self.roots.get(key) {
+            Some(stat) => Some(stat),
+            None => self.archived(key)?.root_ref(key),
+        }
+    }
+
+    /// A directory inside a cached root, deserializing only its own subtree if archived
+    fn find(&self, key: &RootKey, path: &Path) -> Option<Cow<'_, DirStat>> {
+        match self.roots.get(key) {
+            Some(stat) => stat.find(path).map(Cow::Borrowed),
+            None => self.archived(key)?.find(key, path).map(Cow::Owned),
+        }
+    }
+
+    /// Time of the most recent scan in a cached root, read from the index if archived
+    fn newest_scan(&self, key: &RootKey) -> Option<SystemTime> {
+        match self.roots.get(key) {
+            Some(stat) => Some(newest_scan(stat)),
+            None => self.archived(key)?.newest_scan(key),
+        }
+    }
+
+    /// A cached root to change, deserializing it out of the archive first
+    fn root_mut(&mut self, key: &RootKey) -> Option<&mut DirStat> {
+        if !self.roots.contains_key(key) {
+            let stat = self.archived(key)?.root(key)?;
+            self.roots.insert(key.clone(), stat);
+        }
+        self.roots.get_mut(key)
+    }
+
+    /// Check that every archived root is intact
+    fn verify(&self) -> Result<()> {
+        self.archive.as_ref().map_or(Ok(()), CacheArchive::verify)
+    }
+
+    /// Remove a root, keeping it from being merged back in from the file
+    fn remove(&mut self, key: &RootKey) {
+        self.roots.remove(key);
+        self.removed.insert(key.clone());
+    }
 }
 
 /// Public interface for cache operations with lazy writing
+///
+/// Several processes can share one cache file. Loading takes a shared lock
+/// and saving an exclusive one, and a save merges in roots that other
+/// processes saved in the meantime instead of overwriting them.
 pub struct CacheManager {
     cache: Cache,
     cache_path: PathBuf,
@@ -25,9 +164,19 @@ pub struct CacheManager {
 
 impl CacheManager {
     /// Create a new cache manager with specified path
+    ///
+    /// A missing cache file results in an empty cache. So does one that
+    /// cannot be loaded, which is logged as a warning and overwritten on the
+    /// next save; use `open` to fail instead. For a corrupt cache file, one
+    /// that fails its checksum or cannot be decoded, the previous generation
+    /// is used if possible, and the next save moves the corrupt file aside to
+    /// `<cache file>.corrupt` instead of overwriting it.
     pub fn new(cache_path: impl AsRef<Path>) -> Self {
         let cache_path = cache_path.as_ref().to_path_buf();
-        let cache = Self::load_from_file(&cache_path);
+        let cache = Self::load_with_backup(&cache_path).unwrap_or_else(|err| {
+            log::warn!("starting with an empty cache: {}", err);
+            Cache::default()
+        });
 
         Self {
             cache,
@@ -36,79 +185,492 @@ impl CacheManager {
         }
     }
 
-    /// Load cache from file using binary format (falls back to JSON for compatibility)
-    fn load_from_file(cache_path: &Path) -> Cache {
-        // Try binary format first (new format)
-        if let Ok(bytes) = fs::read(cache_path) {
-            if let Ok(cache) = bincode::deserialize::<Cache>(&bytes) {
-                return cache;
-            }
-            // Fall back to JSON for backward compatibility
-            if let Ok(s) = String::from_utf8(bytes) {
-                if let Ok(cache) = serde_json::from_str(&s) {
-                    return cache;
-                }
+    /// Create a cache manager, failing if an existing cache file cannot be loaded
+    ///
+    /// A missing cache file is not an error and results in an empty cache.
+    /// A cache file that cannot be loaded is only an error if the backup of
+    /// the previous generation cannot be loaded either.
+    pub fn open(cache_path: impl AsRef<Path>) -> Result<Self> {
+        let cache_path = cache_path.as_ref().to_path_buf();
+        let cache = Self::load_with_backup(&cache_path)?;
+
+        Ok(Self {
+            cache,
+            cache_path,
+            dirty: false,
+        })
+    }
+
+    /// Path of a file kept next to the cache file, named by appending a suffix
+    fn sibling_path(cache_path: &Path, suffix: &str) -> PathBuf {
+        let mut path = cache_path.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+
+    /// Path of the previous cache generation, kept next to the cache file
+    fn backup_path(cache_path: &Path) -> PathBuf {
+        Self::sibling_path(cache_path, ".bak")
+    }
+
+    /// Take an advisory lock on the lock file next to the cache, held until the file is dropped
+    ///
+    /// The cache file itself is replaced on every save, so it cannot carry
+    /// the lock. Readers share the lock while writers hold it exclusively.
+    fn lock(cache_path: &Path, exclusive: bool) -> io::Result<fs::File> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(Self::sibling_path(cache_path, ".lock"))?;
+        if exclusive {
+            FileExt::lock(&file)?;
+        } else {
+            FileExt::lock_shared(&file)?;
+        }
+        Ok(file)
+    }
+
+    /// Load the cache file, falling back to the previous generation if it cannot be loaded
+    ///
+    /// A missing cache file is an empty cache, not a reason to revive the
+    /// backup. The error of the cache file itself is returned when neither
+    /// can be loaded. A corrupt cache file is left in place, as only a save
+    /// holds the lock that allows moving it aside.
+    fn load_with_backup(cache_path: &Path) -> Result<Cache> {
+        // Without a cache directory there is nothing to read, and a read-only
+        // one cannot hold a lock file, so both read unlocked
+        let _lock = Self::lock(cache_path, false).ok();
+
+        let loaded = Self::load_from_file(cache_path);
+        if let Ok(cache) = loaded {
+            return Ok(Cache {
+                cache_path: Some(cache_path.to_path_buf()),
+                ..cache
+            });
+        }
+
+        let backup_path = Self::backup_path(cache_path);
+        if backup_path.exists() {
+            if let Ok(cache) = Self::load_from_file(&backup_path) {
+                return Ok(cache);
             }
         }
-        Cache::default()
+        loaded
+    }
+
+    /// Keep a corrupt cache file for inspection, out of the way of the next save
+    ///
+    /// Must be called under the exclusive lock, so that no other process
+    /// moves the file or replaces it in the meantime. Files that could not be
+    /// read, or were written in a newer format, are left in place.
+    fn set_aside_if_corrupt(cache_path: &Path, err: &Error) {
+        if !matches!(err, Error::CacheFormat { .. }) {
+            return;
+        }
+        let corrupt_path = Self::sibling_path(cache_path, ".corrupt");
+        match fs::rename(cache_path, &corrupt_path) {
+            Ok(()) => log::warn!("{}, moved it to '{}'", err, corrupt_path.display()),
+            Err(e) => log::warn!("{}, and it could not be moved aside: {}", err, e),
+        }
     }
 
-    /// Save cache to file using binary format
-    pub fn save(&mut self) -> io::Result<()> {
+    /// Load cache from file, migrating layouts written by older versions
+    ///
+    /// A file in the current format is mapped rather than read, leaving its
+    /// roots archived until they are used.
+    fn load_from_file(cache_path: &Path) -> Result<Cache> {
+        let load_error = |source| Error::CacheLoad {
+            path: cache_path.to_path_buf(),
+            source,
+        };
+        let mut file = match fs::File::open(cache_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Cache::default()),
+            Err(source) => return Err(load_error(source)),
+        };
+
+        let mut bytes = Vec::new();
+        file.by_ref()
+            .take(cache_format::VERSION_LEN as u64)
+            .read_to_end(&mut bytes)
+            .map_err(load_error)?;
+        if cache_format::is_current(&bytes) {
+            return Ok(Cache {
+                archive: Some(CacheArchive::open(cache_path, &file)?),
+                ..Default::default()
+            });
+        }
+
+        file.read_to_end(&mut bytes).map_err(load_error)?;
+        cache_format::decode(cache_path, &bytes)
+    }
+
+    /// Save cache to file in the memory-mapped format
+    ///
+    /// Roots saved by other processes since this cache was loaded are merged
+    /// in first. Of two entries for the same root, the one holding the most
+    /// recent scan is kept.
+    pub fn save(&mut self) -> Result<()> {
         if !self.dirty {
             return Ok(()); // Skip if nothing changed
         }
+        self.write(true)
+    }
+
+    /// Write the cache file under an exclusive lock, merging in the file's current roots if asked
+    fn write(&mut self, merge: bool) -> Result<()> {
+        let cache_path = self.cache_path.clone();
+        let save_error = |source| Error::CacheSave {
+            path: cache_path.clone(),
+            source,
+        };
 
         // Ensure parent directory exists
         if let Some(parent) = self.cache_path.parent() {
-            fs::create_dir_all(parent)?;
+            fs::create_dir_all(parent).map_err(save_error)?;
+        }
+        let _lock = Self::lock(&self.cache_path, true).map_err(save_error)?;
+        // Under the exclusive lock the backup can only be opened without locking again
+        if self.cache.archive.is_some() {
+            let cache = &self.cache;
+            cache.backup.get_or_init(|| cache.open_backup(false));
+        }
+        if merge {
+            self.merge_saved_roots();
         }
 
-        // Serialize to binary format (much faster than JSON)
-        let bytes = bincode::serialize(&self.cache)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let temp_path =
+            Self::sibling_path(&self.cache_path, &format!(".tmp.{}", std::process::id()));
+        if let Err(err) = self.write_archive(&temp_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(save_error(err));
+        }
 
-        fs::write(&self.cache_path, bytes)?;
+        // Unmap the file being replaced where the platform refuses to rename over it
+        if cfg!(windows) {
+            self.cache.archive = None;
+            self.cache.backup = OnceLock::new();
+        }
+        if let Err(err) = Self::replace(&self.cache_path, &temp_path) {
+            let _ = fs::remove_file(&temp_path);
+            if self.cache.archive.is_none() {
+                self.reopen_archive();
+            }
+            return Err(save_error(err));
+        }
         self.dirty = false;
+        self.cache.removed.clear();
+        self.cache.backup = OnceLock::new();
+        self.reopen_archive();
         Ok(())
     }
 
-    /// Get a cached directory stat by path
-    pub fn get(&self, path: &Path) -> Option<&DirStat> {
+    /// Serve the roots not loaded into memory from the cache file now in place
+    fn reopen_archive(&mut self) {
+        match Self::load_from_file(&self.cache_path) {
+            Ok(saved) => self.cache.archive = saved.archive,
+            Err(err) => log::warn!("cannot reopen the cache: {}", err),
+        }
+    }
+
+    /// Write every cached root to a new file and sync it
+    ///
+    /// Roots that are still archived are copied without being deserialized.
+    fn write_archive(&self, path: &Path) -> io::Result<()> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        let mut writer = ArchiveWriter::new(file)?;
+        for key in self.cache.keys() {
+            match (self.cache.roots.get(key), self.cache.archived(key)) {
+                (Some(stat), _) => writer.add_tree(key, stat)?,
+                (None, Some(archive)) => {
+                    writer.add_archived(key, archive)?;
+                }
+                // A damaged archive missing from the backup is left out, so the root is rescanned
+                (None, None) => {}
+            }
+        }
+        let file = writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()
+    }
+
+    /// Merge the roots currently in the cache file into this cache
+    ///
+    /// A cache file that cannot be loaded is overwritten, except that a
+    /// corrupt one is moved aside rather than kept as the backup. So is a
+    /// file with a damaged root, after its intact roots are merged.
+    fn merge_saved_roots(&mut self) {
+        let saved = match Self::load_from_file(&self.cache_path) {
+            Ok(saved) => saved,
+            Err(err) => {
+                Self::set_aside_if_corrupt(&self.cache_path, &err);
+                return;
+            }
+        };
+        if let Err(err) = saved.verify() {
+            Self::set_aside_if_corrupt(&self.cache_path, &err);
+        }
+        let keys: Vec<&RootKey> = saved
+            .keys()
+            .filter(|key| !self.cache.removed.contains(*key))
+            .collect();
+        for key in keys {
+            // A saved root inside the tree of one of ours is only taken if it was scanned since
+            let ours = self
+                .cache
+                .newest_scan(key)
+                .or_else(|| self.covering_scan(key));
+            let newer = match ours {
+                Some(ours) => saved.newest_scan(key).is_some_and(|theirs| theirs > ours),
+                None => true,
+            };
+            if newer {
+                if let Some(stat) = saved.root(key) {
+                    self.cache.roots.insert(key.clone(), stat.into_owned());
+                }
+            }
+        }
+    }
+
+    /// Time of the most recent scan in a root held in memory whose tree covers a directory
+    fn covering_scan(&self, key: &RootKey) -> Option<SystemTime> {
+        key.path.ancestors().skip(1).find_map(|ancestor| {
+            let stat = self.cache.roots.get(&RootKey {
+                path: ancestor.to_path_buf(),
+                fingerprint: key.fingerprint,
+            })?;
+            stat.find(&key.path).map(|_| newest_scan(stat))
+        })
+    }
+
+    /// Replace the cache file without ever leaving a partly written one in its place
+    ///
+    /// The new file has already been written and synced next to it. The
+    /// current cache file is linked (or copied, where links are not
+    /// supported) as the `.bak` backup, and the new file is renamed over it,
+    /// so there is always a cache file in place.
+    fn replace(cache_path: &Path, temp_path: &Path) -> io::Result<()> {
+        if cache_path.exists() {
+            let backup_path = Self::backup_path(cache_path);
+            match fs::remove_file(&backup_path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            if fs::hard_link(cache_path, &backup_path).is_err() {
+                fs::copy(cache_path, &backup_path)?;
+            }
+        }
+        fs::rename(temp_path, cache_path)?;
+
+        // Persist the new links themselves
+        #[cfg(unix)]
+        if let Some(parent) = cache_path.parent() {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            fs::File::open(parent)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Get a cached directory stat by path and filter fingerprint
+    ///
+    /// A directory that is not a cached root itself is looked up inside the
+    /// tree of its nearest cached ancestor that holds it.
+    ///
+    /// Roots still in the mapped cache file are walked in place, and only the
+    /// subtree of the directory asked for is deserialized.
+    pub fn get(&self, path: &Path, fingerprint: u64) -> Option<Cow<'_, DirStat>> {
         // Normalize path for lookup
         let lookup_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
-        self.cache.roots.get(&lookup_path)
+        self.get_root(&lookup_path, fingerprint).or_else(|| {
+            self.covering_ancestor(&lookup_path, fingerprint)
+                .map(|(_, stat)| stat)
+        })
+    }
+
+    /// Get a cached directory stat by path and filter fingerprint, borrowed from the cache
+    ///
+    /// Like `get`, except that a root still in the mapped cache file is
+    /// deserialized as a whole the first time, and kept until the next save.
+    pub fn get_ref(&self, path: &Path, fingerprint: u64) -> Option<&DirStat> {
+        let lookup_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let key = |path: &Path| RootKey {
+            path: path.to_path_buf(),
+            fingerprint,
+        };
+        self.cache.root_ref(&key(&lookup_path)).or_else(|| {
+            lookup_path.ancestors().skip(1).find_map(|ancestor| {
+                self.cache
+                    .root_ref(&key(ancestor))?
+                    .find(&lookup_path)
+                    .filter(|stat| !stat.is_mount_point())
+            })
+        })
+    }
+
+    /// Get a cached root by its canonical path, without looking inside other roots
+    pub(crate) fn get_root(&self, path: &Path, fingerprint: u64) -> Option<Cow<'_, DirStat>> {
+        self.cache.root(&RootKey {
+            path: path.to_path_buf(),
+            fingerprint,
+        })
+    }
+
+    /// Find the closest cached root strictly above a canonical path whose tree holds it
+    ///
+    /// A root whose scan left the directory out, because its filters excluded
+    /// it or it was recorded as a mount point, is passed over for one further up.
+    /// Returns the root's key along with the directory's entry in its tree.
+    fn covering_ancestor(
+        &self,
+        path: &Path,
+        fingerprint: u64,
+    ) -> Option<(RootKey, Cow<'_, DirStat>)> {
+        path.ancestors().skip(1).find_map(|ancestor| {
+            let key = RootKey {
+                path: ancestor.to_path_buf(),
+                fingerprint,
+            };
+            let stat = self
+                .cache
+                .find(&key, path)
+                .filter(|stat| !stat.is_mount_point())?;
+            Some((key, stat))
+        })
+    }
+
+    /// Build a starting cache for a directory from cached roots below it
+    ///
+    /// The roots are placed into a tree of placeholders at their positions, so
+    /// a scan of the directory reuses them while reading everything else.
+    /// Returns `None` when no cached root lies below the directory.
+    pub(crate) fn stitch_nested_roots(&self, path: &Path, fingerprint: u64) -> Option<DirStat> {
+        let mut nested: Vec<&RootKey> = self
+            .cache
+            .keys()
+            .filter(|key| {
+                key.fingerprint == fingerprint && key.path != path && key.path.starts_with(path)
+            })
+            .collect();
+        if nested.is_empty() {
+            return None;
+        }
+
+        // Outermost roots first; roots inside one already placed are already covered
+        nested.sort_by_key(|key| key.path.components().count());
+        let mut tree = DirStat::placeholder(path);
+        for key in nested {
+            if tree.find(&key.path).is_none() {
+                // A root inside a recorded mount point cannot be placed and is skipped
+                if let Some(stat) = self.cache.root(key) {
+                    let _ = tree.insert_descendant(stat.into_owned());
+                }
+            }
+        }
+        Some(tree)
+    }
+
+    /// Remove cached roots below a root whose tree now covers them
+    ///
+    /// Roots at directories the tree does not hold, such as mount points it
+    /// skipped or directories it excluded, are kept.
+    fn remove_covered_roots(&mut self, path: &Path, fingerprint: u64) {
+        let Some(root) = self.cache.roots.get(&RootKey {
+            path: path.to_path_buf(),
+            fingerprint,
+        }) else {
+            return;
+        };
+        let covered: Vec<RootKey> = self
+            .cache
+            .keys()
+            .filter(|key| {
+                key.fingerprint == fingerprint
+                    && key.path != path
+                    && key.path.starts_with(path)
+                    && root
+                        .find(&key.path)
+                        .is_some_and(|stat| !stat.is_mount_point())
+            })
+            .cloned()
+            .collect();
+        for key in covered {
+            self.cache.remove(&key);
+            self.dirty = true;
+        }
     }
 
     /// Insert or update a directory stat in the cache
     /// Path is automatically canonicalized to ensure consistent lookups
-    #[allow(dead_code)]
-    pub fn insert(&mut self, path: PathBuf, stats: DirStat) {
+    pub fn insert(&mut self, path: PathBuf, fingerprint: u64, stats: DirStat) {
         // Canonicalize the path before storing to ensure consistent lookups
         let canonical_path = path.canonicalize().unwrap_or(path);
-        self.cache.roots.insert(canonical_path, stats);
+        let key = RootKey {
+            path: canonical_path,
+            fingerprint,
+        };
+        self.cache.roots.insert(key, stats);
         self.dirty = true;
     }
 
     /// Update an existing entry with new stats
-    /// This is just a convenience wrapper around insert
-    pub fn update(&mut self, path: &Path, new_stats: DirStat) {
-        self.insert(path.to_path_buf(), new_stats);
+    ///
+    /// A directory that lies inside the tree of a cached ancestor (and is not
+    /// a cached root itself) replaces its entry in that tree, and the totals of
+    /// the directories above it are adjusted. Otherwise it is stored as a root,
+    /// replacing any cached roots below it that its tree now covers.
+    pub fn update(&mut self, path: &Path, fingerprint: u64, mut new_stats: DirStat) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let key = RootKey {
+            path: path.clone(),
+            fingerprint,
+        };
+        if !self.cache.contains(&key) {
+            let ancestor = self
+                .covering_ancestor(&path, fingerprint)
+                .map(|(key, _)| key)
+                .and_then(|key| self.cache.root_mut(&key));
+            if let Some(ancestor) = ancestor {
+                match ancestor.graft(new_stats) {
+                    Ok(()) => {
+                        self.dirty = true;
+                        return;
+                    }
+                    Err(stats) => new_stats = *stats,
+                }
+            }
+        }
+        self.insert(path.clone(), fingerprint, new_stats);
+        self.remove_covered_roots(&path, fingerprint);
     }
 
-    /// Clear all cache contents
-    pub fn clear(&mut self) -> io::Result<()> {
+    /// Clear all cache contents, including the backup of the previous generation
+    ///
+    /// Roots saved by other processes are discarded as well.
+    pub fn clear(&mut self) -> Result<()> {
         self.cache = Cache::default();
         self.dirty = true;
-        self.save()
+        self.write(false)?;
+        Self::remove_if_exists(&Self::backup_path(&self.cache_path))
     }
 
-    /// Delete the cache file
-    pub fn delete(&self) -> io::Result<()> {
-        if self.cache_path.exists() {
-            fs::remove_file(&self.cache_path)
-        } else {
-            Ok(())
+    /// Delete the cache file and its backup
+    pub fn delete(&self) -> Result<()> {
+        Self::remove_if_exists(&self.cache_path)?;
+        Self::remove_if_exists(&Self::backup_path(&self.cache_path))
+    }
+
+    fn remove_if_exists(path: &Path) -> Result<()> {
+        match fs::remove_file(path) {
+            Err(source) if source.kind() != io::ErrorKind::NotFound => Err(Error::CacheSave {
+                path: path.to_path_buf(),
+                source,
+            }),
+            _ => Ok(()),
         }
     }
 
@@ -118,6 +680,17 @@ impl CacheManager {
     }
 }
 
+/// Time of the most recent scan anywhere in a tree
+///
+/// A root's own `last_scan` is not advanced when a rescanned subdirectory is
+/// grafted into it, so the whole tree is searched.
+pub(crate) fn newest_scan(stat: &DirStat) -> SystemTime {
+    stat.iter_depth_first()
+        .map(DirStat::last_scan)
+        .max()
+        .unwrap_or(stat.last_scan)
+}
+
 // Implement Drop to auto-save on destruction
 impl Drop for CacheManager {
     fn drop(&mut self) {
@@ -145,18 +718,37 @@ mod tests {
         let test_stat = DirStat {
             path: PathBuf::from("/test/path"),
             total_size: 1000,
+            allocated_size: 4096,
             file_count: 10,
+            unique_size: 1000,
+            unique_allocated: 4096,
+            shared_size: 0,
+            shared_allocated: 0,
+            hard_links: Vec::new(),
+            symlink_count: 0,
+            dir_symlinks: 0,
+            followed_symlinks: false,
+            ignored_size: 0,
+            ignored_allocated: 0,
+            ignored_file_count: 0,
+            ignored: false,
+            ignore_rules: 0,
+            own_size: 0,
+            own_allocated: 0,
+            incomplete: false,
+            cancelled: false,
+            errors: Vec::new(),
             last_scan: SystemTime::now(),
+            mount_point: false,
             children: HashMap::new(),
         };
 
-        cache_mgr.insert(PathBuf::from("/test/path"), test_stat.clone());
+        cache_mgr.insert(PathBuf::from("/test/path"), 0, test_stat.clone());
 
         // Test get
-        let retrieved = cache_mgr.get(Path::new("/test/path"));
-        assert!(retrieved.is_some());
-        assert_eq!(retrieved.unwrap().total_size, 1000);
-        assert_eq!(retrieved.unwrap().file_count, 10);
+        let retrieved = cache_mgr.get(Path::new("/test/path"), 0).unwrap();
+        assert_eq!(retrieved.total_size, 1000);
+        assert_eq!(retrieved.file_count, 10);
 
         // Test save
         cache_mgr.save()?;
@@ -164,10 +756,52 @@ mod tests {
 
         // Test loading from file
         let cache_mgr2 = CacheManager::new(&cache_file);
-        let retrieved2 = cache_mgr2.get(Path::new("/test/path"));
+        let retrieved2 = cache_mgr2.get(Path::new("/test/path"), 0);
         assert!(retrieved2.is_some());
         assert_eq!(retrieved2.unwrap().total_size, 1000);
 
+        // Entries scanned with a different filter are kept apart
+        assert!(cache_mgr2.get(Path::new("/test/path"), 42).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_roots_are_reused_and_consolidated() -> crate::Result<()> {
+        use crate::scanner::{self, ScanOptions};
+
+        let temp_dir = TempDir::new().unwrap();
+        let data = temp_dir.path().canonicalize().unwrap().join("data");
+        fs::create_dir_all(data.join("a/deep")).unwrap();
+        fs::create_dir_all(data.join("b")).unwrap();
+        fs::write(data.join("a/deep/one.txt"), "12345").unwrap();
+        fs::write(data.join("b/two.txt"), "123").unwrap();
+        fs::write(data.join("top.txt"), "1").unwrap();
+
+        let mut cache_mgr = CacheManager::new(temp_dir.path().join("cache.bin"));
+        for dir in ["a", "b"] {
+            let stats = scanner::scan_directory(&data.join(dir), None)?;
+            cache_mgr.update(&data.join(dir), 0, stats);
+        }
+        assert_eq!(cache_mgr.cache.roots.len(), 2);
+
+        let stitched = cache_mgr.stitch_nested_roots(&data, 0).unwrap();
+        assert!(stitched.is_incomplete());
+        assert_eq!(stitched.total_size(), 8);
+
+        // Only the new top level is read; both nested roots are reused
+        let options = ScanOptions::default();
+        let (stats, report) =
+            scanner::scan_directory_with_report(&data, Some(&stitched), &options, None)?;
+        assert_eq!(stats.total_size(), 9);
+        assert!(!stats.is_incomplete());
+        assert_eq!(report.dirs_scanned, 1);
+        assert_eq!(report.dirs_reused, 3);
+
+        cache_mgr.update(&data, 0, stats);
+        assert_eq!(cache_mgr.cache.roots.len(), 1);
+        assert_eq!(cache_mgr.get(&data.join("a"), 0).unwrap().total_size(), 5);
+
         Ok(())
     }
 
@@ -181,17 +815,37 @@ mod tests {
         let test_stat = DirStat {
             path: PathBuf::from("/test"),
             total_size: 500,
+            allocated_size: 4096,
             file_count: 5,
+            unique_size: 500,
+            unique_allocated: 4096,
+            shared_size: 0,
+            shared_allocated: 0,
+            hard_links: Vec::new(),
+            symlink_count: 0,
+            dir_symlinks: 0,
+            followed_symlinks: false,
+            ignored_size: 0,
+            ignored_allocated: 0,
+            ignored_file_count: 0,
+            ignored: false,
+            ignore_rules: 0,
+            own_size: 0,
+            own_allocated: 0,
+            incomplete: false,
+            cancelled: false,
+            errors: Vec::new(),
             last_scan: SystemTime::now(),
+            mount_point: false,
             children: HashMap::new(),
         };
 
-        cache_mgr.insert(PathBuf::from("/test"), test_stat);
+        cache_mgr.insert(PathBuf::from("/test"), 0, test_stat);
         cache_mgr.save()?;
 
         // Test clear
         cache_mgr.clear()?;
-        assert!(cache_mgr.get(Path::new("/test")).is_none());
+        assert!(cache_mgr.get(Path::new("/test"), 0).is_none());
 
         // Test delete
         cache_mgr.delete()?;
@@ -199,4 +853,372 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_save_keeps_previous_generation() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cache_file = temp_dir.path().join("cache.bin");
+        let backup_file = temp_dir.path().join("cache.bin.bak");
+        let data = temp_dir.path().join("data");
+        fs::create_dir(&data)?;
+
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        cache_mgr.insert(
+            data.clone(),
+            0,
+            crate::scanner::scan_directory(&data, None)?,
+        );
+        cache_mgr.save()?;
+        assert!(!backup_file.exists());
+
+        cache_mgr.insert(
+            data.clone(),
+            1,
+            crate::scanner::scan_directory(&data, None)?,
+        );
+        cache_mgr.save()?;
+        assert!(backup_file.exists());
+        // No temporary files are left behind
+        for entry in fs::read_dir(temp_dir.path())? {
+            assert!(!entry?.file_name().to_string_lossy().contains(".tmp"));
+        }
+
+        // A truncated cache file falls back to the previous generation
+        let bytes = fs::read(&cache_file)?;
+        fs::write(&cache_file, &bytes[..bytes.len() / 2])?;
+        let cache_mgr = CacheManager::open(&cache_file)?;
+        assert!(cache_mgr.get(&data, 0).is_some());
+        assert!(cache_mgr.get(&data, 1).is_none());
+
+        // A missing cache file starts empty instead
+        fs::remove_file(&cache_file)?;
+        assert!(CacheManager::new(&cache_file).get(&data, 0).is_none());
+
+        cache_mgr.delete()?;
+        assert!(!backup_file.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_replace_keeps_cache_usable() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cache_file = temp_dir.path().join("cache.bin");
+        let data = temp_dir.path().canonicalize()?.join("data");
+        fs::create_dir(&data)?;
+
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        cache_mgr.insert(
+            data.clone(),
+            0,
+            crate::scanner::scan_directory(&data, None)?,
+        );
+        cache_mgr.save()?;
+
+        // A directory in place of the backup makes replacing the cache file fail
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        fs::create_dir_all(temp_dir.path().join("cache.bin.bak/keep"))?;
+        cache_mgr.insert(
+            data.clone(),
+            1,
+            crate::scanner::scan_directory(&data, None)?,
+        );
+        assert!(matches!(
+            cache_mgr.save(),
+            Err(crate::Error::CacheSave { .. })
+        ));
+        for entry in fs::read_dir(temp_dir.path())? {
+            assert!(!entry?.file_name().to_string_lossy().contains(".tmp"));
+        }
+
+        // Archived roots are still served, and the next save succeeds
+        assert!(cache_mgr.get(&data, 0).is_some());
+        fs::remove_dir_all(temp_dir.path().join("cache.bin.bak"))?;
+        cache_mgr.save()?;
+        let cache_mgr = CacheManager::new(&cache_file);
+        assert!(cache_mgr.get(&data, 0).is_some());
+        assert!(cache_mgr.get(&data, 1).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_merges_roots_saved_by_others() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cache_file = temp_dir.path().join("cache.bin");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(data.join("sub"))?;
+        let older = crate::scanner::scan_directory(&data, None)?;
+        let newer = crate::scanner::scan_directory(&data, None)?;
+        assert!(newer.last_scan() > older.last_scan());
+
+        // Two users of the same cache file, each scanning something different
+        let mut first = CacheManager::new(&cache_file);
+        let mut second = CacheManager::new(&cache_file);
+        first.insert(data.clone(), 0, newer.clone());
+        second.insert(data.clone(), 1, older.clone());
+        first.save()?;
+        second.save()?;
+        let merged = CacheManager::new(&cache_file);
+        assert!(merged.get_root(&data, 0).is_some());
+        assert!(merged.get_root(&data, 1).is_some());
+
+        // The more recent scan of the same root wins, whichever is saved last
+        second.insert(data.clone(), 0, older.clone());
+        second.save()?;
+        let merged = CacheManager::new(&cache_file);
+        assert_eq!(
+            merged.get_root(&data, 0).unwrap().last_scan(),
+            newer.last_scan()
+        );
+
+        // Roots a manager removed itself are not merged back in
+        let mut third = CacheManager::new(&cache_file);
+        third.insert(
+            data.join("sub"),
+            0,
+            newer.find(data.join("sub")).unwrap().clone(),
+        );
+        third.save()?;
+        let mut fourth = CacheManager::new(&cache_file);
+        fourth.update(&data, 0, newer.clone());
+        fourth.save()?;
+        assert!(CacheManager::new(&cache_file)
+            .get_root(&data.join("sub"), 0)
+            .is_none());
+
+        // A root saved inside the tree of one held here is dropped, unless it is newer
+        let mut fifth = CacheManager::new(&cache_file);
+        let mut sixth = CacheManager::new(&cache_file);
+        let sub = newer.find(data.join("sub")).unwrap().clone();
+        fifth.update(&data, 0, newer.clone());
+        sixth.insert(
+            data.join("sub"),
+            0,
+            older.find(data.join("sub")).unwrap().clone(),
+        );
+        sixth.save()?;
+        fifth.save()?;
+        assert!(CacheManager::new(&cache_file)
+            .get_root(&data.join("sub"), 0)
+            .is_none());
+        let newest = crate::scanner::scan_directory(&data.join("sub"), None)?;
+        assert!(newest.last_scan() > sub.last_scan());
+        sixth.insert(data.join("sub"), 0, newest);
+        sixth.save()?;
+        fifth.save()?;
+        assert!(CacheManager::new(&cache_file)
+            .get_root(&data.join("sub"), 0)
+            .is_some());
+
+        // Clearing discards everything, including roots saved by others
+        first.clear()?;
+        assert!(CacheManager::new(&cache_file).cache.keys().next().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_reports_corrupt_cache() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cache_file = temp_dir.path().join("cache.bin");
+
+        // A missing cache is simply empty
+        assert!(CacheManager::open(&cache_file).is_ok());
+
+        fs::write(&cache_file, [0xff, 0x00, 0x13])?;
+        assert!(matches!(
+            CacheManager::open(&cache_file),
+            Err(Error::CacheFormat { .. })
+        ));
+
+        // The lenient constructor starts over with an empty cache, keeping the corrupt file
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        assert!(cache_mgr.cache.roots.is_empty());
+        assert!(cache_file.exists());
+        cache_mgr.dirty = true;
+        cache_mgr.save()?;
+        assert_eq!(
+            fs::read(temp_dir.path().join("cache.bin.corrupt"))?,
+            [0xff, 0x00, 0x13]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_mismatch_rebuilds_from_backup() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cache_file = temp_dir.path().join("cache.bin");
+        let data = temp_dir.path().join("data");
+        fs::create_dir(&data)?;
+        let stats = crate::scanner::scan_directory(&data, None)?;
+
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        cache_mgr.insert(data.clone(), 0, stats.clone());
+        cache_mgr.save()?;
+        cache_mgr.insert(data.clone(), 1, stats.clone());
+        cache_mgr.save()?;
+
+        // A flipped bit that still decodes is caught by the checksum
+        let mut bytes = fs::read(&cache_file)?;
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+        fs::write(&cache_file, &bytes)?;
+        assert!(CacheManager::open(&cache_file)?.get(&data, 1).is_none());
+        assert!(cache_file.exists());
+
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        assert!(cache_mgr.get(&data, 0).is_some());
+        assert!(cache_mgr.get(&data, 1).is_none());
+
+        // The next save sets the corrupt file aside, keeping the good generation as the backup
+        cache_mgr.insert(data.clone(), 2, stats);
+        cache_mgr.save()?;
+        assert_eq!(fs::read(temp_dir.path().join("cache.bin.corrupt"))?, bytes);
+        let backup = temp_dir.path().join("cache.bin.bak");
+        assert!(CacheManager::open(&backup)?.get(&data, 0).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_archived_roots_are_read_in_place() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cache_file = temp_dir.path().join("cache.bin");
+        let data = temp_dir.path().canonicalize()?.join("data");
+        let other = temp_dir.path().canonicalize()?.join("other");
+        fs::create_dir_all(data.join("sub"))?;
+        fs::create_dir(&other)?;
+        fs::write(data.join("sub/file.txt"), "12345")?;
+
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        for dir in [&data, &other] {
+            cache_mgr.insert(dir.clone(), 0, crate::scanner::scan_directory(dir, None)?);
+        }
+        cache_mgr.save()?;
+
+        // Lookups are answered from the mapped file without loading whole roots
+        let cache_mgr = CacheManager::new(&cache_file);
+        assert!(cache_mgr.cache.roots.is_empty());
+        assert_eq!(cache_mgr.get(&data.join("sub"), 0).unwrap().total_size(), 5);
+        assert!(cache_mgr.get(&other, 0).is_some());
+        assert!(cache_mgr.cache.roots.is_empty());
+
+        // A root whose archive is damaged is read from the backup instead
+        let key = RootKey {
+            path: data.clone(),
+            fingerprint: 0,
+        };
+        drop(cache_mgr);
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        cache_mgr.insert(
+            other.clone(),
+            0,
+            crate::scanner::scan_directory(&other, None)?,
+        );
+        cache_mgr.save()?;
+        let offset = cache_mgr.cache.archive.as_ref().unwrap().offset(&key);
+        drop(cache_mgr);
+        let backup = fs::read(temp_dir.path().join("cache.bin.bak"))?;
+        let mut bytes = fs::read(&cache_file)?;
+        bytes[offset] ^= 0x01;
+        fs::write(&cache_file, &bytes)?;
+
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        assert!(cache_mgr.cache.contains(&key));
+        assert_eq!(cache_mgr.get(&data.join("sub"), 0).unwrap().total_size(), 5);
+        assert!(cache_mgr.get(&other, 0).is_some());
+        cache_mgr.insert(
+            data.clone(),
+            1,
+            crate::scanner::scan_directory(&data, None)?,
+        );
+        cache_mgr.save()?;
+
+        // The damaged file is set aside instead of becoming the backup, and
+        // the root is saved again from the backup
+        assert_eq!(fs::read(temp_dir.path().join("cache.bin.corrupt"))?, bytes);
+        assert_eq!(fs::read(temp_dir.path().join("cache.bin.bak"))?, backup);
+        let cache_mgr = CacheManager::new(&cache_file);
+        assert_eq!(cache_mgr.cache.keys().count(), 3);
+        assert!(cache_mgr.cache.roots.is_empty());
+        assert!(cache_mgr.get(&data, 0).is_some());
+        assert!(cache_mgr.get(&data, 1).is_some());
+        assert!(cache_mgr.get(&other, 0).is_some());
+
+        // Without an intact copy in the backup, the damaged root is dropped and the rest are kept
+        let offset = cache_mgr.cache.archive.as_ref().unwrap().offset(&key);
+        drop(cache_mgr);
+        fs::remove_file(temp_dir.path().join("cache.bin.bak"))?;
+        let mut bytes = fs::read(&cache_file)?;
+        bytes[offset] ^= 0x01;
+        fs::write(&cache_file, &bytes)?;
+
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        assert!(!cache_mgr.cache.contains(&key));
+        assert!(cache_mgr.get(&data, 0).is_none());
+        assert!(cache_mgr.get(&data, 1).is_some());
+        cache_mgr.dirty = true;
+        cache_mgr.save()?;
+
+        let cache_mgr = CacheManager::new(&cache_file);
+        assert_eq!(cache_mgr.cache.keys().count(), 2);
+        assert!(cache_mgr.get(&data, 0).is_none());
+        assert!(cache_mgr.get(&data, 1).is_some());
+        assert!(cache_mgr.get(&other, 0).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_reads_damaged_root_from_backup() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cache_file = temp_dir.path().join("cache.bin");
+        let root = temp_dir.path().canonicalize()?;
+        let dirs = [root.join("a"), root.join("b"), root.join("c")];
+        for dir in &dirs {
+            fs::create_dir(dir)?;
+        }
+        let key = RootKey {
+            path: dirs[0].clone(),
+            fingerprint: 0,
+        };
+
+        // Two generations, so that the backup holds an intact copy of the first root
+        for _ in 0..2 {
+            let mut cache_mgr = CacheManager::new(&cache_file);
+            for dir in &dirs[..2] {
+                cache_mgr.insert(dir.clone(), 0, crate::scanner::scan_directory(dir, None)?);
+            }
+            cache_mgr.save()?;
+        }
+        let offset = CacheManager::new(&cache_file)
+            .cache
+            .archive
+            .as_ref()
+            .unwrap()
+            .offset(&key);
+        let mut bytes = fs::read(&cache_file)?;
+        bytes[offset] ^= 0x01;
+        fs::write(&cache_file, &bytes)?;
+
+        // Saving finds the damage itself, under the exclusive lock
+        let mut cache_mgr = CacheManager::new(&cache_file);
+        cache_mgr.insert(
+            dirs[2].clone(),
+            0,
+            crate::scanner::scan_directory(&dirs[2], None)?,
+        );
+        cache_mgr.save()?;
+        drop(cache_mgr);
+
+        let cache_mgr = CacheManager::new(&cache_file);
+        assert_eq!(cache_mgr.cache.keys().count(), 3);
+        for dir in &dirs {
+            assert!(cache_mgr.get(dir, 0).is_some());
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,703 @@
+//! Memory-mapped cache file with one zero-copy archive per cached root
+//!
+//! After the `cache_format` magic number and version, the header holds the
+//! CRC-32, offset and length of an index at the end of the file. The index
+//! lists every cached root with the time of its most recent scan and the
+//! position and CRC-32 of its archive. Each root is archived with rkyv as a
+//! flat list of directories in pre-order, children referring to their
+//! entries by index.
+//!
+//! Loading maps the file and reads only the index. A lookup copies the
+//! archive of one root out of the mapping and validates it the first time it
+//! is used, walks it in place to the directory asked for and deserializes
+//! only that subtree, so the time to answer a query does not grow with the
+//! rest of the cache.
+
+use memmap2::Mmap;
+use rkyv::{rancor, util::AlignedVec, Archive};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+
+use crate::cache::{newest_scan, RootKey};
+use crate::cache_format::{FORMAT_VERSION, MAGIC};
+use crate::error::{Error, Result};
+use crate::scanner::{DirStat, HardLink};
+
+/// Magic number, version, index checksum, index offset and index length
+pub(crate) const HEADER_LEN: usize = MAGIC.len() + 4 + 4 + 8 + 8;
+
+/// Archives start at multiples of this, as rkyv requires aligned data
+const ALIGN: u64 = 16;
+
+/// Position and checksum of one root's archive, as stored in the index
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    key: RootKey,
+    newest_scan: SystemTime,
+    offset: u64,
+    len: u64,
+    checksum: u32,
+}
+
+/// A root in a mapped cache file
+#[derive(Debug)]
+struct Entry {
+    newest_scan: SystemTime,
+    offset: usize,
+    len: usize,
+    checksum: u32,
+    /// The archive copied out of the mapping once used, or `None` if it is damaged
+    archive: OnceLock<Option<AlignedVec>>,
+    /// The whole root, once deserialized to be borrowed
+    root: OnceLock<DirStat>,
+}
+
+/// A cache file in the current format, mapped into memory
+#[derive(Debug)]
+pub(crate) struct CacheArchive {
+    path: PathBuf,
+    file: File,
+    map: Mmap,
+    roots: HashMap<RootKey, Entry>,
+}
+
+impl CacheArchive {
+    /// Map a cache file in the current format and read its index
+    pub(crate) fn open(path: &Path, file: &File) -> Result<Self> {
+        let format_error = |source: Box<dyn std::error::Error + Send + Sync>| Error::CacheFormat {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let load_error = |source| Error::CacheLoad {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        // SAFETY: reading the mapping is only sound while the file keeps its
+        // length. This crate replaces cache files by renaming a new file over
+        // them, but other writers may truncate one in place: release 0.1.0
+        // saves with `fs::write`, and network file systems can truncate too.
+        // Reading a page past the new end raises SIGBUS and kills the process,
+        // which no checksum can catch. So the index is read once here, and each
+        // root's archive is copied out once, after checking that the file still
+        // has its mapped length; a truncation racing that check remains fatal.
+        let map = unsafe { Mmap::map(file) }.map_err(load_error)?;
+        let file = file.try_clone().map_err(load_error)?;
+        if map.len() < HEADER_LEN {
+            return Err(format_error("truncated header".into()));
+        }
+
+        let field = |at: usize, len: usize| &map[MAGIC.len() + 4 + at..][..len];
+        let checksum = u32::from_le_bytes(field(0, 4).try_into().unwrap());
+        let offset = u64::from_le_bytes(field(4, 8).try_into().unwrap());
+        let len = u64::from_le_bytes(field(12, 8).try_into().unwrap());
+        if offset < HEADER_LEN as u64 || offset.checked_add(len) != Some(map.len() as u64) {
+            return Err(format_error(
+                format!(
+                    "index at {}..{} does not end the {}-byte file",
+                    offset,
+                    offset.saturating_add(len),
+                    map.len()
+                )
+                .into(),
+            ));
+        }
+
+        let index = &map[offset as usize..];
+        let found = crc32fast::hash(index);
+        if found != checksum {
+            return Err(format_error(
+                format!("index checksum is {:08x}, expected {:08x}", found, checksum).into(),
+            ));
+        }
+        let index: Vec<IndexEntry> = bincode::deserialize(index).map_err(|e| format_error(e))?;
+
+        let mut roots = HashMap::with_capacity(index.len());
+        for entry in index {
+            let in_bounds = entry.offset >= HEADER_LEN as u64
+                && entry.offset % ALIGN == 0
+                && entry
+                    .offset
+                    .checked_add(entry.len)
+                    .is_some_and(|end| end <= offset);
+            if !in_bounds {
+                return Err(format_error(
+                    format!("archive of '{}' is out of bounds", entry.key.path.display()).into(),
+                ));
+            }
+            roots.insert(
+                entry.key,
+                Entry {
+                    newest_scan: entry.newest_scan,
+                    offset: entry.offset as usize,
+                    len: entry.len as usize,
+                    checksum: entry.checksum,
+                    archive: OnceLock::new(),
+                    root: OnceLock::new(),
+                },
+            );
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            map,
+            roots,
+        })
+    }
+
+    /// Whether the file holds an intact archive of a root, copying it out the first time
+    pub(crate) fn contains(&self, key: &RootKey) -> bool {
+        self.tree(key).is_some()
+    }
+
+    /// Check the archive of every root, failing on the first one that is damaged
+    ///
+    /// Archives not used yet are checked in the mapping rather than copied out.
+    pub(crate) fn verify(&self) -> Result<()> {
+        match self
+            .roots
+            .iter()
+            .find(|(key, entry)| self.intact_bytes(key, entry).is_none())
+        {
+            Some((key, _)) => Err(Error::CacheFormat {
+                path: self.path.clone(),
+                source: format!("archive of '{}' is corrupt", key.path.display()).into(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether a root is listed in the index, whether or not its archive is intact
+    pub(crate) fn lists(&self, key: &RootKey) -> bool {
+        self.roots.contains_key(key)
+    }
+
+    /// Keys of all roots listed in the file, whether or not their archives are intact
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &RootKey> {
+        self.roots.keys()
+    }
+
+    /// Time of the most recent scan anywhere in a root's tree, from the index
+    pub(crate) fn newest_scan(&self, key: &RootKey) -> Option<SystemTime> {
+        self.roots.get(key).map(|entry| entry.newest_scan)
+    }
+
+    /// Deserialize a whole root
+    pub(crate) fn root(&self, key: &RootKey) -> Option<DirStat> {
+        let tree = self.tree(key)?;
+        Some(tree.dir_stat(0))
+    }
+
+    /// Borrow a whole root, deserializing it the first time
+    pub(crate) fn root_ref(&self, key: &RootKey) -> Option<&DirStat> {
+        let entry = self.roots.get(key)?;
+        let tree = self.tree(key)?;
+        Some(entry.root.get_or_init(|| tree.dir_stat(0)))
+    }
+
+    /// Deserialize the subtree of a directory inside a root, leaving the rest of the root archived
+    pub(crate) fn find(&self, key: &RootKey, path: &Path) -> Option<DirStat> {
+        let tree = self.tree(key)?;
+        tree.find(path).map(|index| tree.dir_stat(index))
+    }
+
+    /// Position of a root's archive in the file
+    #[cfg(test)]
+    pub(crate) fn offset(&self, key: &RootKey) -> usize {
+        self.roots[key].offset
+    }
+
+    /// The archive of a root and its checksum, if it is intact
+    fn archived_bytes(&self, key: &RootKey) -> Option<(&[u8], u32)> {
+        let entry = self.roots.get(key)?;
+        Some((self.intact_bytes(key, entry)?, entry.checksum))
+    }
+
+    /// Access a root's archive if it is intact
+    fn tree(&self, key: &RootKey) -> Option<&ArchivedTree> {
+        let entry = self.roots.get(key)?;
+        let archive = self.archive(key, entry)?;
+
+        // SAFETY: the copy passed validation and is never modified
+        Some(unsafe { rkyv::access_unchecked::<ArchivedTree>(archive) })
+    }
+
+    /// Copy a root's archive out of the mapping and check it, the first time only
+    ///
+    /// A root whose archive is damaged is logged and treated as not cached,
+    /// so it is rescanned and rewritten.
+    fn archive<'a>(&'a self, key: &RootKey, entry: &'a Entry) -> Option<&'a AlignedVec> {
+        entry
+            .archive
+            .get_or_init(|| {
+                let mut archive = AlignedVec::with_capacity(entry.len);
+                archive.extend_from_slice(self.mapped(entry)?);
+                self.check(key, entry, &archive).then_some(archive)
+            })
+            .as_ref()
+    }
+
+    /// A root's archive if it is intact, checked in the mapping unless already copied out
+    fn intact_bytes<'a>(&'a self, key: &RootKey, entry: &'a Entry) -> Option<&'a [u8]> {
+        match entry.archive.get() {
+            Some(archive) => archive.as_deref(),
+            None => self
+                .mapped(entry)
+                .filter(|bytes| self.check(key, entry, bytes)),
+        }
+    }
+
+    /// A root's archive in the mapping, if the file still has the length it was mapped with
+    fn mapped(&self, entry: &Entry) -> Option<&[u8]> {
+        let len = self.file.metadata().map(|meta| meta.len()).unwrap_or(0);
+        if len != self.map.len() as u64 {
+            log::warn!(
+                "cache '{}' was changed in place, ignoring its roots",
+                self.path.display()
+            );
+            return None;
+        }
+        Some(&self.map[entry.offset..entry.offset + entry.len])
+    }
+
+    /// Check a root's archive against its checksum and validate it
+    fn check(&self, key: &RootKey, entry: &Entry, bytes: &[u8]) -> bool {
+        let valid = crc32fast::hash(bytes) == entry.checksum
+            && rkyv::access::<ArchivedTree, rancor::Error>(bytes)
+                .is_ok_and(ArchivedTree::is_well_formed);
+        if !valid {
+            log::warn!(
+                "cache '{}' is corrupt, ignoring its entry for '{}'",
+                self.path.display(),
+                key.path.display()
+            );
+        }
+        valid
+    }
+}
+
+/// Writes a cache file in the current format
+pub(crate) struct ArchiveWriter<W> {
+    out: W,
+    position: u64,
+    index: Vec<IndexEntry>,
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    /// Start a cache file, leaving room for the header
+    pub(crate) fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(&[0; HEADER_LEN])?;
+        Ok(Self {
+            out,
+            position: HEADER_LEN as u64,
+            index: Vec::new(),
+        })
+    }
+
+    /// Archive and add a root
+    pub(crate) fn add_tree(&mut self, key: &RootKey, stat: &DirStat) -> io::Result<()> {
+        let mut nodes = Vec::new();
+        Node::push(stat, &mut nodes);
+        let bytes = rkyv::to_bytes::<rancor::Error>(&Tree { nodes })
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let checksum = crc32fast::hash(&bytes);
+        self.add_bytes(key, newest_scan(stat), &bytes, checksum)
+    }
+
+    /// Copy a root from another cache file without deserializing it
+    ///
+    /// Returns `false`, adding nothing, if the root's archive is not intact.
+    pub(crate) fn add_archived(
+        &mut self,
+        key: &RootKey,
+        archive: &CacheArchive,
+    ) -> io::Result<bool> {
+        let (Some((bytes, checksum)), Some(newest_scan)) =
+            (archive.archived_bytes(key), archive.newest_scan(key))
+        else {
+            return Ok(false);
+        };
+        self.add_bytes(key, newest_scan, bytes, checksum)?;
+        Ok(true)
+    }
+
+    fn add_bytes(
+        &mut self,
+        key: &RootKey,
+        newest_scan: SystemTime,
+        bytes: &[u8],
+        checksum: u32,
+    ) -> io::Result<()> {
+        let padding = self.position.next_multiple_of(ALIGN) - self.position;
+        self.out
+            .write_all(&[0; ALIGN as usize][..padding as usize])?;
+        self.position += padding;
+
+        self.index.push(IndexEntry {
+            key: key.clone(),
+            newest_scan,
+            offset: self.position,
+            len: bytes.len() as u64,
+            checksum,
+        });
+        self.out.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Write the index and header, returning the output
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        let index = bincode::serialize(&self.index)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.out.write_all(&index)?;
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header.extend_from_slice(&crc32fast::hash(&index).to_le_bytes());
+        header.extend_from_slice(&self.position.to_le_bytes());
+        header.extend_from_slice(&(index.len() as u64).to_le_bytes());
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(&header)?;
+        Ok(self.out)
+    }
+}
+
+/// A cached root: its directories in pre-order, the root first
+#[derive(Archive, rkyv::Serialize)]
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+/// One directory of a `DirStat` tree, with every field that is cached
+///
+/// Adding a cached field to `DirStat` means adding it here as well, along
+/// with a new format version.
+#[derive(Archive, rkyv::Serialize)]
+struct Node {
+    path: Vec<u8>,
+    total_size: u64,
+    allocated_size: u64,
+    file_count: u64,
+    unique_size: u64,
+    unique_allocated: u64,
+    shared_size: u64,
+    shared_allocated: u64,
+    hard_links: Vec<Link>,
+    symlink_count: u64,
+    dir_symlinks: u64,
+    followed_symlinks: bool,
+    ignored_size: u64,
+    ignored_allocated: u64,
+    ignored_file_count: u64,
+    ignored: bool,
+    ignore_rules: u64,
+    own_size: u64,
+    own_allocated: u64,
+    incomplete: bool,
+    last_scan_secs: u64,
+    last_scan_nanos: u32,
+    mount_point: bool,
+    children: Vec<u32>,
+}
+
+#[derive(Archive, rkyv::Serialize)]
+struct Link {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    allocated: u64,
+}
+
+impl Node {
+    /// Append a directory and its subdirectories, returning the directory's index
+    fn push(stat: &DirStat, nodes: &mut Vec<Node>) -> u32 {
+        let index = nodes.len();
+        let last_scan = stat
+            .last_scan
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        nodes.push(Node {
+            path: path_to_bytes(&stat.path),
+            total_size: stat.total_size,
+            allocated_size: stat.allocated_size,
+            file_count: stat.file_count,
+            unique_size: stat.unique_size,
+            unique_allocated: stat.unique_allocated,
+            shared_size: stat.shared_size,
+            shared_allocated: stat.shared_allocated,
+            hard_links: stat
+                .hard_links
+                .iter()
+                .map(|link| Link {
+                    dev: link.dev,
+                    ino: link.ino,
+                    size: link.size,
+                    allocated: link.allocated,
+                })
+                .collect(),
+            symlink_count: stat.symlink_count,
+            dir_symlinks: stat.dir_symlinks,
+            followed_symlinks: stat.followed_symlinks,
+            ignored_size: stat.ignored_size,
+            ignored_allocated: stat.ignored_allocated,
+            ignored_file_count: stat.ignored_file_count,
+            ignored: stat.ignored,
+            ignore_rules: stat.ignore_rules,
+            own_size: stat.own_size,
+            own_allocated: stat.own_allocated,
+            incomplete: stat.incomplete,
+            last_scan_secs: last_scan.as_secs(),
+            last_scan_nanos: last_scan.subsec_nanos(),
+            mount_point: stat.mount_point,
+            children: Vec::new(),
+        });
+
+        let children = stat
+            .children
+            .values()
+            .map(|child| Node::push(child, nodes))
+            .collect();
+        nodes[index].children = children;
+        index as u32
+    }
+}
+
+impl ArchivedTree {
+    /// Whether there is a root and every child comes after its parent, so walks end
+    fn is_well_formed(&self) -> bool {
+        let len = self.nodes.len();
+        len > 0
+            && self.nodes.iter().enumerate().all(|(index, node)| {
+                node.children
+                    .iter()
+                    .all(|child| (index + 1..len).contains(&(child.to_native() as usize)))
+            })
+    }
+
+    /// Index of a directory inside the tree, walking down from the root
+    fn find(&self, path: &Path) -> Option<usize> {
+        let root = path_from_bytes(&self.nodes.first()?.path);
+        let relative = path.strip_prefix(&root).ok()?;
+
+        let mut index = 0;
+        let mut current = root;
+        for component in relative.components() {
+            current.push(component);
+            let wanted = path_to_bytes(&current);
+            index = self.nodes[index]
+                .children
+                .iter()
+                .map(|child| child.to_native() as usize)
+                .find(|&child| self.nodes[child].path.as_slice() == wanted.as_slice())?;
+        }
+        Some(index)
+    }
+
+    /// Deserialize the directory at an index and its subdirectories
+    fn dir_stat(&self, index: usize) -> DirStat {
+        let node = &self.nodes[index];
+        let path = path_from_bytes(&node.path);
+        let children = node
+            .children
+            .iter()
+            .map(|child| {
+                let child = self.dir_stat(child.to_native() as usize);
+                (child.path.clone(), child)
+            })
+            .collect();
+
+        DirStat {
+            path,
+            total_size: node.total_size.to_native(),
+            allocated_size: node.allocated_size.to_native(),
+            file_count: node.file_count.to_native(),
+            unique_size: node.unique_size.to_native(),
+            unique_allocated: node.unique_allocated.to_native(),
+            shared_size: node.shared_size.to_native(),
+            shared_allocated: node.shared_allocated.to_native(),
+            hard_links: node
+                .hard_links
+                .iter()
+                .map(|link| HardLink {
+                    dev: link.dev.to_native(),
+                    ino: link.ino.to_native(),
+                    size: link.size.to_native(),
+                    allocated: link.allocated.to_native(),
+                })
+                .collect(),
+            symlink_count: node.symlink_count.to_native(),
+            dir_symlinks: node.dir_symlinks.to_native(),
+            followed_symlinks: node.followed_symlinks,
+            ignored_size: node.ignored_size.to_native(),
+            ignored_allocated: node.ignored_allocated.to_native(),
+            ignored_file_count: node.ignored_file_count.to_native(),
+            ignored: node.ignored,
+            ignore_rules: node.ignore_rules.to_native(),
+            own_size: node.own_size.to_native(),
+            own_allocated: node.own_allocated.to_native(),
+            incomplete: node.incomplete,
+            cancelled: false,
+            errors: Vec::new(),
+            last_scan: SystemTime::UNIX_EPOCH
+                + Duration::new(
+                    node.last_scan_secs.to_native(),
+                    node.last_scan_nanos.to_native(),
+                ),
+            mount_point: node.mount_point,
+            children,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+/// Paths are stored as UTF-8 on platforms without byte paths
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::Cursor};
+    use tempfile::TempDir;
+
+    fn key(path: &Path) -> RootKey {
+        RootKey {
+            path: path.to_path_buf(),
+            fingerprint: 0,
+        }
+    }
+
+    /// Archive roots into a file and map it
+    fn write_archive(dir: &Path, roots: &[&DirStat]) -> (PathBuf, CacheArchive) {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        for stat in roots {
+            writer.add_tree(&key(stat.path()), stat).unwrap();
+        }
+        let path = dir.join("cache.bin");
+        fs::write(&path, writer.finish().unwrap().into_inner()).unwrap();
+        let archive = CacheArchive::open(&path, &File::open(&path).unwrap()).unwrap();
+        (path, archive)
+    }
+
+    #[test]
+    fn test_roots_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(data.join("a/deep")).unwrap();
+        fs::create_dir_all(data.join("b")).unwrap();
+        fs::write(data.join("a/deep/one.txt"), "12345").unwrap();
+        fs::write(data.join("b/two.txt"), "123").unwrap();
+        let mut stat = crate::scanner::scan_directory(&data, None).unwrap();
+        stat.hard_links.push(HardLink {
+            dev: 1,
+            ino: 2,
+            size: 3,
+            allocated: 4096,
+        });
+
+        let (_, archive) = write_archive(temp_dir.path(), &[&stat]);
+        let as_json = |stat: &DirStat| serde_json::to_value(stat).unwrap();
+        assert_eq!(as_json(&archive.root(&key(&data)).unwrap()), as_json(&stat));
+        assert_eq!(
+            as_json(&archive.find(&key(&data), &data.join("a/deep")).unwrap()),
+            as_json(stat.find(data.join("a/deep")).unwrap())
+        );
+        assert!(archive.find(&key(&data), &data.join("a/none")).is_none());
+        assert_eq!(archive.newest_scan(&key(&data)), Some(newest_scan(&stat)));
+    }
+
+    #[test]
+    fn test_corrupt_root_is_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = DirStat::placeholder(Path::new("/first"));
+        let second = DirStat::placeholder(Path::new("/second"));
+        let (path, archive) = write_archive(temp_dir.path(), &[&first, &second]);
+        let offset = archive.offset(&key(first.path()));
+        drop(archive);
+
+        // Flip a bit inside the first root's archive, leaving the index intact
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[offset] ^= 0x01;
+        fs::write(&path, &bytes).unwrap();
+        let archive = CacheArchive::open(&path, &File::open(&path).unwrap()).unwrap();
+        assert!(!archive.contains(&key(first.path())));
+        assert!(archive.root(&key(first.path())).is_none());
+        assert!(archive.contains(&key(second.path())));
+        assert!(matches!(archive.verify(), Err(Error::CacheFormat { .. })));
+        assert!(archive.root(&key(second.path())).is_some());
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        assert!(!writer.add_archived(&key(first.path()), &archive).unwrap());
+        assert!(writer.add_archived(&key(second.path()), &archive).unwrap());
+    }
+
+    #[test]
+    fn test_truncated_file_is_not_read() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = DirStat::placeholder(Path::new("/first"));
+        let second = DirStat::placeholder(Path::new("/second"));
+        let (path, archive) = write_archive(temp_dir.path(), &[&first, &second]);
+        assert!(archive.root_ref(&key(first.path())).is_some());
+
+        // Truncated in place, as an older release saving over the file would;
+        // only the root copied out before is still served
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(HEADER_LEN as u64).unwrap();
+        assert!(archive.root(&key(first.path())).is_some());
+        assert!(archive.root(&key(second.path())).is_none());
+        assert!(matches!(archive.verify(), Err(Error::CacheFormat { .. })));
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        assert!(writer.add_archived(&key(first.path()), &archive).unwrap());
+        assert!(!writer.add_archived(&key(second.path()), &archive).unwrap());
+    }
+
+    #[test]
+    fn test_rejects_damaged_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let stat = DirStat::placeholder(Path::new("/data"));
+        let (path, archive) = write_archive(temp_dir.path(), &[&stat]);
+        drop(archive);
+        let bytes = fs::read(&path).unwrap();
+
+        for damaged in [
+            bytes[..bytes.len() - 1].to_vec(),
+            bytes[..HEADER_LEN - 1].to_vec(),
+            [&bytes[..], &[0]].concat(),
+        ] {
+            fs::write(&path, &damaged).unwrap();
+            assert!(matches!(
+                CacheArchive::open(&path, &File::open(&path).unwrap()),
+                Err(Error::CacheFormat { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Cache file versions and migrations from older layouts
+//!
+//! A cache file starts with the 8-byte `MAGIC` and the format version as a
+//! little-endian `u32`. The current format, version 1 and the first with a
+//! header, is a memory-mapped archive described in `cache_archive`.
+//!
+//! The only older layout is layout 0, written by release 0.1.0 without a
+//! header. It keyed roots by path alone and only recorded apparent sizes and
+//! file counts. It is decoded in full when loaded and written back in the
+//! current format on the next save.
+
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path, path::PathBuf, time::SystemTime};
+
+use crate::cache::{Cache, RootKey};
+use crate::error::{Error, Result};
+use crate::scanner::DirStat;
+
+/// First bytes of every cache file written with a header
+pub(crate) const MAGIC: [u8; 8] = *b"ACMEDUC\0";
+
+/// Version of the format written by this build
+///
+/// Changing the layout of the archived `DirStat` requires a new version, with
+/// a frozen copy of the previous layout and a migration from it below.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+/// Length of the magic number and version shared by every header
+pub(crate) const VERSION_LEN: usize = MAGIC.len() + 4;
+
+/// Whether the start of a file is the header of the current format
+pub(crate) fn is_current(start: &[u8]) -> bool {
+    start
+        .strip_prefix(&MAGIC)
+        .and_then(|rest| rest.first_chunk::<4>())
+        == Some(&FORMAT_VERSION.to_le_bytes())
+}
+
+/// Decode a cache file in an older format read from `path`, migrating it
+pub(crate) fn decode(path: &Path, bytes: &[u8]) -> Result<Cache> {
+    let format_error = |source| Error::CacheFormat {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let Some(header) = bytes.strip_prefix(&MAGIC) else {
+        return decode_v0(bytes).map(migrate_v0).map_err(format_error);
+    };
+    let Some(version) = header.first_chunk::<4>() else {
+        return Err(format_error("truncated header".into()));
+    };
+    Err(Error::CacheVersion {
+        path: path.to_path_buf(),
+        found: u32::from_le_bytes(*version),
+        expected: FORMAT_VERSION,
+    })
+}
+
+/// Decode a file from before the header, which 0.1.0 wrote as bincode and read as JSON too
+fn decode_v0(
+    bytes: &[u8],
+) -> std::result::Result<CacheV0, Box<dyn std::error::Error + Send + Sync>> {
+    let bincode_err = match bincode::deserialize::<CacheV0>(bytes) {
+        Ok(cache) => return Ok(cache),
+        Err(e) => e,
+    };
+    if let Ok(s) = std::str::from_utf8(bytes) {
+        if let Ok(cache) = serde_json::from_str::<CacheV0>(s) {
+            return Ok(cache);
+        }
+    }
+    Err(bincode_err)
+}
+
+/// Layout 0, written by release 0.1.0
+#[derive(Deserialize)]
+struct CacheV0 {
+    roots: HashMap<PathBuf, DirStatV0>,
+    #[serde(rename = "version")]
+    _version: u32,
+}
+
+#[derive(Deserialize)]
+struct DirStatV0 {
+    path: PathBuf,
+    total_size: u64,
+    file_count: u64,
+    last_scan: SystemTime,
+    children: HashMap<PathBuf, DirStatV0>,
+}
+
+/// Key roots by path and the fingerprint of unfiltered scans, the only kind 0.1.0 made
+///
+/// Layout 0 has no allocated sizes, so they are taken to equal the apparent
+/// sizes until a directory is rescanned.
+fn migrate_v0(cache: CacheV0) -> Cache {
+    let roots = cache
+        .roots
+        .into_iter()
+        .map(|(path, stat)| {
+            let key = RootKey {
+                path,
+                fingerprint: 0,
+            };
+            (key, migrate_dir_v0(stat))
+        })
+        .collect();
+    Cache {
+        roots,
+        ..Default::default()
+    }
+}
+
+fn migrate_dir_v0(stat: DirStatV0) -> DirStat {
+    DirStat {
+        path: stat.path,
+        total_size: stat.total_size,
+        allocated_size: stat.total_size,
+        file_count: stat.file_count,
+        unique_size: stat.total_size,
+        unique_allocated: stat.total_size,
+        shared_size: 0,
+        shared_allocated: 0,
+        hard_links: Vec::new(),
+        symlink_count: 0,
+        dir_symlinks: 0,
+        followed_symlinks: false,
+        ignored_size: 0,
+        ignored_allocated: 0,
+        ignored_file_count: 0,
+        ignored: false,
+        ignore_rules: 0,
+        own_size: 0,
+        own_allocated: 0,
+        incomplete: false,
+        cancelled: false,
+        errors: Vec::new(),
+        last_scan: stat.last_scan,
+        mount_point: false,
+        children: stat
+            .children
+            .into_iter()
+            .map(|(path, child)| (path, migrate_dir_v0(child)))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct WriteV0 {
+        roots: HashMap<PathBuf, WriteDirV0>,
+        version: u32,
+    }
+
+    #[derive(Serialize)]
+    struct WriteDirV0 {
+        path: PathBuf,
+        total_size: u64,
+        file_count: u64,
+        last_scan: SystemTime,
+        children: HashMap<PathBuf, WriteDirV0>,
+    }
+
+    /// A cache file written by 0.1.0 in JSON, with one root and one subdirectory
+    const V0_JSON: &str = r#"{"roots":{"/data":{"path":"/data","total_size":100,"file_count":5,"last_scan":{"secs_since_epoch":0,"nanos_since_epoch":0},"children":{"/data/sub":{"path":"/data/sub","total_size":40,"file_count":2,"last_scan":{"secs_since_epoch":0,"nanos_since_epoch":0},"children":{}}}}},"version":0}"#;
+
+    #[test]
+    fn test_migrates_layout_0() {
+        let path = Path::new("cache.bin");
+        let sub = WriteDirV0 {
+            path: PathBuf::from("/data/sub"),
+            total_size: 40,
+            file_count: 2,
+            last_scan: SystemTime::UNIX_EPOCH,
+            children: HashMap::new(),
+        };
+        let root = WriteDirV0 {
+            path: PathBuf::from("/data"),
+            total_size: 100,
+            file_count: 5,
+            last_scan: SystemTime::UNIX_EPOCH,
+            children: HashMap::from([(sub.path.clone(), sub)]),
+        };
+        let v0 = WriteV0 {
+            roots: HashMap::from([(root.path.clone(), root)]),
+            version: 0,
+        };
+
+        // Layout 0 in both encodings 0.1.0 could read
+        for bytes in [
+            bincode::serialize(&v0).unwrap(),
+            V0_JSON.as_bytes().to_vec(),
+        ] {
+            let cache = decode(path, &bytes).unwrap();
+            assert_eq!(cache.roots.len(), 1);
+            let stat = &cache.roots[&RootKey {
+                path: PathBuf::from("/data"),
+                fingerprint: 0,
+            }];
+            assert_eq!(stat.total_size(), 100);
+            assert_eq!(stat.allocated_size(), 100);
+            assert_eq!(stat.file_count(), 5);
+            assert_eq!(stat.find("/data/sub").unwrap().total_size(), 40);
+        }
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let path = Path::new("cache.bin");
+        for version in [0, FORMAT_VERSION + 1] {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&u32::to_le_bytes(version));
+            bytes.extend_from_slice(&[0; 16]);
+            assert!(matches!(
+                decode(path, &bytes),
+                Err(Error::CacheVersion { found, expected: FORMAT_VERSION, .. })
+                    if found == version
+            ));
+        }
+
+        assert!(matches!(
+            decode(path, &MAGIC),
+            Err(Error::CacheFormat { .. })
+        ));
+        assert!(matches!(
+            decode(path, b"not a cache"),
+            Err(Error::CacheFormat { .. })
+        ));
+    }
+}
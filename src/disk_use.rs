@@ -1,20 +1,51 @@
 //! High-level disk usage analysis interface combining cache and scanner
 
-use std::{io, path::Path};
+use std::{
+    borrow::Cow,
+    io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 use crate::cache::CacheManager;
-use crate::scanner::{self, DirStat};
+use crate::error::{Error, Result};
+use crate::progress::ProgressObserver;
+use crate::report::ScanReport;
+use crate::scanner::{self, DirStat, ScanError, ScanOptions, SymlinkPolicy};
+use crate::summary::ScanSummary;
 
 /// Main interface for disk usage analysis with caching support
 pub struct DiskUse {
     cache_manager: CacheManager,
+    last_errors: Vec<ScanError>,
+    last_report: ScanReport,
+    progress: Option<Box<dyn ProgressObserver>>,
 }
 
 impl DiskUse {
     /// Create a new DiskUse instance with the specified cache file path
     pub fn new(cache_path: impl AsRef<Path>) -> Self {
+        let started = Instant::now();
+        let cache_manager = CacheManager::new(cache_path);
+        Self::with_cache_manager(cache_manager, started)
+    }
+
+    /// Create a new DiskUse instance, failing if an existing cache file cannot be loaded
+    pub fn open(cache_path: impl AsRef<Path>) -> Result<Self> {
+        let started = Instant::now();
+        let cache_manager = CacheManager::open(cache_path)?;
+        Ok(Self::with_cache_manager(cache_manager, started))
+    }
+
+    fn with_cache_manager(cache_manager: CacheManager, load_started: Instant) -> Self {
         Self {
-            cache_manager: CacheManager::new(cache_path),
+            cache_manager,
+            last_errors: Vec::new(),
+            last_report: ScanReport {
+                cache_load_time: load_started.elapsed(),
+                ..Default::default()
+            },
+            progress: None,
         }
     }
 
@@ -23,86 +54,260 @@ impl DiskUse {
         Self::new(crate::get_default_cache_path())
     }
 
+    /// Report the progress of subsequent scans to `observer`
+    ///
+    /// The observer is called from scanner threads after every directory.
+    pub fn set_progress_observer(&mut self, observer: impl ProgressObserver + 'static) {
+        self.progress = Some(Box::new(observer));
+    }
+
+    /// Stop reporting scan progress
+    pub fn clear_progress_observer(&mut self) {
+        self.progress = None;
+    }
+
     /// Scan a directory and return its total size in bytes
     ///
     /// This method automatically:
     /// - Loads from cache
     /// - Scans only changed directories
     /// - Saves the updated cache
-    pub fn scan(&mut self, path: impl AsRef<Path>) -> io::Result<u64> {
-        self.scan_with_options(path, false)
+    pub fn scan(&mut self, path: impl AsRef<Path>) -> Result<u64> {
+        self.scan_with_options(path, &ScanOptions::default())
     }
 
-    /// Scan a directory with options for ignoring cache
+    /// Scan a directory with explicit options
     ///
     /// # Arguments
     /// * `path` - The directory path to scan
-    /// * `ignore_cache` - If true, performs a fresh scan without using cache
+    /// * `options` - Scan options; `ignore_cache` performs a fresh scan without
+    ///   using the cache and `size_mode` selects which size is returned
+    ///
+    /// Unreadable entries below the root do not fail the scan; they are left out
+    /// of the total and listed by `last_scan_errors`. With `options.strict` the
+    /// scan instead fails with `Error::PartialScan` after caching the result.
+    ///
+    /// A scan stopped through `options.cancel` or `options.time_budget` fails
+    /// with `Error::Cancelled`; the subtrees it completed are still cached, so
+    /// the next scan resumes from them.
+    ///
+    /// Scanning a directory inside a previously scanned one reuses that part
+    /// of the cached tree and writes the result back into it, updating the
+    /// ancestor's totals. Conversely, cached results for directories inside
+    /// `path` are reused and merged into its entry. Patterns containing `/` and
+    /// ignore files make results depend on where the scan starts, so such scans
+    /// are cached separately.
     pub fn scan_with_options(
         &mut self,
         path: impl AsRef<Path>,
-        ignore_cache: bool,
-    ) -> io::Result<u64> {
-        let path = path.as_ref();
+        options: &ScanOptions,
+    ) -> Result<u64> {
+        self.scan_inner(path.as_ref(), options)
+            .map(|(size, _, _)| size)
+    }
+
+    /// Scan a directory and return its size, file and directory counts and tree
+    ///
+    /// Behaves like `scan_with_options`, but everything comes from the same
+    /// walk, so nothing is scanned twice even when `ignore_cache` is set.
+    /// The tree is borrowed from the cache rather than copied, so the summary
+    /// also carries the scan's report and errors.
+    pub fn scan_summary(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: &ScanOptions,
+    ) -> Result<ScanSummary<'_>> {
+        let (size, uncached, path) = self.scan_inner(path.as_ref(), options)?;
+        let tree = match uncached {
+            Some(tree) => Cow::Owned(tree),
+            None => {
+                let fingerprint = options.fingerprint();
+                let cached = if options.is_root_independent() {
+                    self.cache_manager.get(&path, fingerprint)
+                } else {
+                    self.cache_manager.get_root(&path, fingerprint)
+                };
+                cached.ok_or_else(|| Error::Io {
+                    source: io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "the scanned tree is missing from the cache",
+                    ),
+                    path,
+                })?
+            }
+        };
+        Ok(ScanSummary::new(
+            size,
+            tree,
+            &self.last_report,
+            &self.last_errors,
+        ))
+    }
+
+    /// Scan, update the cache and return the reported size, plus the tree if it was not cached
+    ///
+    /// Also returns the canonical path the tree was cached under.
+    fn scan_inner(
+        &mut self,
+        path: &Path,
+        options: &ScanOptions,
+    ) -> Result<(u64, Option<DirStat>, PathBuf)> {
+        // An unfollowed symlink root would be cached under its target's canonical path
+        let unfollowed_link = options.symlinks == SymlinkPolicy::Never && path.is_symlink();
+        let ignore_cache = options.ignore_cache || unfollowed_link;
 
         // Normalize path to avoid issues with symlinks and /private on macOS
         let path_buf = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
 
-        // Get existing cache entry for this root (unless ignoring cache)
-        let old_entry = if ignore_cache {
+        // Get existing cache entry for this root and filter (unless ignoring cache),
+        // falling back to its entry in a cached ancestor's tree when that scans the same
+        let fingerprint = options.fingerprint();
+        let nested = options.is_root_independent();
+
+        let cached = if ignore_cache {
             None
+        } else if nested {
+            self.cache_manager.get(&path_buf, fingerprint)
+        } else {
+            self.cache_manager.get_root(&path_buf, fingerprint)
+        };
+
+        // Without either, start from any cached roots that lie below this one
+        let stitched = if nested && !ignore_cache && cached.is_none() {
+            self.cache_manager
+                .stitch_nested_roots(&path_buf, fingerprint)
         } else {
-            self.cache_manager.get(&path_buf)
+            None
         };
+        let old_entry = cached.or(stitched.as_ref().map(Cow::Borrowed));
+
+        // Scan under the canonical path so the tree's paths match the cache keys
+        let scan_path = if unfollowed_link { path } else { &path_buf };
 
         // Scan the directory (will use cache for unchanged subdirectories)
-        let new_entry = scanner::scan_directory(path, old_entry)?;
+        let (new_entry, report) = scanner::scan_directory_with_report(
+            scan_path,
+            old_entry.as_deref(),
+            options,
+            self.progress.as_deref(),
+        )?;
+        self.last_report = ScanReport {
+            cache_load_time: self.last_report.cache_load_time,
+            ..report
+        };
+        self.last_errors = new_entry.errors().into_iter().cloned().collect();
+        let cancelled = new_entry.is_cancelled();
 
-        // Get the total size before potentially moving new_entry
-        let total_size = new_entry.total_size();
+        // Get the reported size before potentially moving new_entry
+        let total_size = if options.dedup_hard_links {
+            new_entry.deduplicated_size(options.size_mode)
+        } else {
+            new_entry.size(options.size_mode)
+        };
 
         // Update the cache with new results (unless ignoring cache)
-        if !ignore_cache {
-            self.cache_manager.update(&path_buf, new_entry);
+        let tree = if ignore_cache {
+            Some(new_entry)
+        } else {
+            if nested {
+                self.cache_manager.update(&path_buf, fingerprint, new_entry);
+            } else {
+                self.cache_manager
+                    .insert(path_buf.clone(), fingerprint, new_entry);
+            }
             // Cache will auto-save on drop
+            None
+        };
+
+        if cancelled {
+            return Err(Error::Cancelled {
+                path: path_buf,
+                size: total_size,
+            });
+        }
+
+        if options.strict && !self.last_errors.is_empty() {
+            return Err(Error::PartialScan {
+                path: path_buf,
+                size: total_size,
+                errors: self.last_errors.clone(),
+            });
         }
 
-        Ok(total_size)
+        Ok((total_size, tree, path_buf))
+    }
+
+    /// Get statistics about the work done by the most recent scan
+    ///
+    /// The save time is filled in by the next `save_cache` call.
+    pub fn last_scan_report(&self) -> &ScanReport {
+        &self.last_report
+    }
+
+    /// Get the errors encountered by the most recent scan
+    ///
+    /// A non-empty list means the reported total is missing the listed entries.
+    pub fn last_scan_errors(&self) -> &[ScanError] {
+        &self.last_errors
     }
 
     /// Get detailed statistics for a previously scanned path
+    ///
+    /// The path may be any directory inside a previously scanned one; it is
+    /// looked up in the cached tree of the nearest scanned ancestor that holds it.
+    ///
+    /// Returns the result of an unfiltered scan; see `get_stats_with_options`
+    /// for results scanned with include/exclude patterns.
     pub fn get_stats(&self, path: impl AsRef<Path>) -> Option<&DirStat> {
-        self.cache_manager.get(path.as_ref())
+        self.get_stats_with_options(path, &ScanOptions::default())
+    }
+
+    /// Get detailed statistics for a path previously scanned with the given options
+    pub fn get_stats_with_options(
+        &self,
+        path: impl AsRef<Path>,
+        options: &ScanOptions,
+    ) -> Option<&DirStat> {
+        self.cache_manager
+            .get_ref(path.as_ref(), options.fingerprint())
     }
 
     /// Get file count for a path
     ///
     /// # Arguments
     /// * `path` - The path to get file count for
-    /// * `ignore_cache` - If true, counts files directly from filesystem instead of using cache
-    pub fn get_file_count(&self, path: impl AsRef<Path>, ignore_cache: bool) -> io::Result<u64> {
-        if ignore_cache {
-            scanner::count_files(path.as_ref())
+    /// * `options` - Scan options; with `ignore_cache` the files are counted by
+    ///   scanning the filesystem instead of reading the cache
+    ///
+    /// To count files during a scan without walking the tree again, use
+    /// `scan_summary` instead.
+    pub fn get_file_count(&self, path: impl AsRef<Path>, options: &ScanOptions) -> Result<u64> {
+        if options.ignore_cache {
+            scanner::scan_directory_with_options(path.as_ref(), None, options)
+                .map(|stats| stats.file_count())
         } else {
             Ok(self
-                .get_stats(path)
+                .get_stats_with_options(path, options)
                 .map(|stats| stats.file_count())
                 .unwrap_or(0))
         }
     }
 
     /// Save the current cache to disk
-    pub fn save_cache(&mut self) -> io::Result<()> {
-        self.cache_manager.save()
+    pub fn save_cache(&mut self) -> Result<()> {
+        let started = Instant::now();
+        self.cache_manager.save()?;
+        self.last_report.cache_save_time = started.elapsed();
+        Ok(())
     }
 
     /// Clear all cache contents
-    pub fn clear_cache(&mut self) -> io::Result<()> {
+    pub fn clear_cache(&mut self) -> Result<()> {
         self.cache_manager.clear()
     }
 
     /// Delete the cache file
-    pub fn delete_cache(&self) -> io::Result<()> {
+    pub fn delete_cache(&self) -> Result<()> {
         self.cache_manager.delete()
     }
 
@@ -115,7 +320,7 @@ impl DiskUse {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
+    use std::{fs, io};
     use tempfile::TempDir;
 
     fn create_test_directory_structure(base: &Path) -> io::Result<()> {
@@ -158,7 +363,8 @@ mod tests {
             let _size2 = disk_use.scan(&canonical_test_dir)?;
             assert_eq!(_size2, 71);
 
-            let file_count = disk_use.get_file_count(&canonical_test_dir, false)?;
+            let file_count =
+                disk_use.get_file_count(&canonical_test_dir, &ScanOptions::default())?;
             assert_eq!(file_count, 5);
         }
 
@@ -183,12 +389,233 @@ mod tests {
 
         let _size2 = disk_use.scan(&test_dir)?;
 
-        let size3 = disk_use.scan_with_options(&test_dir, true)?;
+        let options = ScanOptions {
+            ignore_cache: true,
+            ..Default::default()
+        };
+        let size3 = disk_use.scan_with_options(&test_dir, &options)?;
         assert_eq!(size3, 82);
 
         Ok(())
     }
 
+    #[test]
+    fn test_scan_summary_walks_once() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_directory_structure(&test_dir)?;
+
+        let mut disk_use = DiskUse::new(temp_dir.path().join("cache.bin"));
+        let options = ScanOptions {
+            ignore_cache: true,
+            ..Default::default()
+        };
+        let summary = disk_use.scan_summary(&test_dir, &options)?;
+
+        assert_eq!(summary.size(), 71);
+        assert_eq!(summary.file_count(), 5);
+        assert_eq!(summary.dir_count(), 4);
+        assert_eq!(summary.stats().total_size(), 71);
+        // Root, subdir1, subdir2 and nested, each read exactly once
+        assert_eq!(disk_use.last_scan_report().dirs_scanned, 4);
+        assert!(disk_use.get_stats(&test_dir).is_none());
+
+        // A cached scan hands back the tree it stores, without copying it
+        let cached = disk_use.scan_summary(&test_dir, &ScanOptions::default())?;
+        assert_eq!(cached.dir_count(), 4);
+        assert_eq!(cached.report().dirs_scanned, 4);
+        let tree: *const DirStat = cached.stats();
+        assert!(std::ptr::eq(tree, disk_use.get_stats(&test_dir).unwrap()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sub_path_uses_ancestor_cache() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_directory_structure(&test_dir)?;
+        let test_dir = test_dir.canonicalize()?;
+        let subdir2 = test_dir.join("subdir2");
+
+        let mut disk_use = DiskUse::new(temp_dir.path().join("cache.bin"));
+        assert_eq!(disk_use.scan(&test_dir)?, 71);
+
+        // Sub-paths are answered from the ancestor's tree
+        assert_eq!(disk_use.get_stats(&subdir2).unwrap().total_size(), 29);
+        assert_eq!(
+            disk_use
+                .get_stats(subdir2.join("nested"))
+                .unwrap()
+                .file_count(),
+            1
+        );
+        assert!(disk_use.get_stats(test_dir.join("missing")).is_none());
+
+        // Scanning a sub-path reuses the ancestor's subtree
+        assert_eq!(disk_use.scan(&subdir2)?, 29);
+        assert_eq!(disk_use.last_scan_report().dirs_scanned, 0);
+        assert_eq!(disk_use.last_scan_report().dirs_reused, 2);
+
+        // and writes its changes back into the ancestor
+        fs::write(subdir2.join("nested/new.txt"), "0123456789")?;
+        assert_eq!(disk_use.scan(&subdir2)?, 39);
+        assert_eq!(disk_use.get_stats(&test_dir).unwrap().total_size(), 81);
+        assert_eq!(disk_use.get_stats(&test_dir).unwrap().file_count(), 6);
+        assert_eq!(disk_use.scan(&test_dir)?, 81);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_excluded_sub_path_is_cached_separately() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_directory_structure(&test_dir)?;
+        fs::create_dir(test_dir.join("skipme"))?;
+        fs::write(test_dir.join("skipme/big.bin"), vec![0u8; 5000])?;
+
+        let mut disk_use = DiskUse::new(temp_dir.path().join("cache.bin"));
+        let options = ScanOptions {
+            exclude: vec!["skipme".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(disk_use.scan_with_options(&test_dir, &options)?, 71);
+
+        // The filter is not applied to the root of a scan, so the excluded
+        // directory is counted, but kept out of the ancestor's tree
+        let skipme = test_dir.join("skipme");
+        assert_eq!(disk_use.scan_with_options(&skipme, &options)?, 5000);
+        assert_eq!(
+            disk_use
+                .get_stats_with_options(&test_dir, &options)
+                .unwrap()
+                .total_size(),
+            71
+        );
+        assert_eq!(
+            disk_use
+                .get_stats_with_options(&skipme, &options)
+                .unwrap()
+                .total_size(),
+            5000
+        );
+        assert_eq!(disk_use.scan_with_options(&test_dir, &options)?, 71);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sub_path_skips_ancestor_that_excluded_it() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir_all(test_dir.join("a/b/c"))?;
+        fs::write(test_dir.join("a/b/c/file.txt"), "12345")?;
+        let test_dir = test_dir.canonicalize()?;
+
+        // Anchored to each scan root, so `a`'s scan excludes a/b/c but `test`'s does not
+        let mut disk_use = DiskUse::new(temp_dir.path().join("cache.bin"));
+        let options = ScanOptions {
+            exclude: vec!["b/c".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(disk_use.scan_with_options(&test_dir, &options)?, 5);
+        assert_eq!(disk_use.scan_with_options(test_dir.join("a"), &options)?, 0);
+
+        let stats = disk_use.get_stats_with_options(test_dir.join("a/b/c"), &options);
+        assert_eq!(stats.unwrap().total_size(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_count_without_cache() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_directory_structure(&test_dir)?;
+
+        // Counted by a fresh scan, which applies the filters and leaves the cache alone
+        let disk_use = DiskUse::new(temp_dir.path().join("cache.bin"));
+        let mut options = ScanOptions {
+            ignore_cache: true,
+            ..Default::default()
+        };
+        assert_eq!(disk_use.get_file_count(&test_dir, &options)?, 5);
+        options.exclude = vec!["subdir2".to_string()];
+        assert_eq!(disk_use.get_file_count(&test_dir, &options)?, 3);
+        assert!(disk_use.get_stats(&test_dir).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filtered_scans_cached_separately() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        let cache_file = temp_dir.path().join("cache.bin");
+
+        fs::create_dir(&test_dir)?;
+        create_test_directory_structure(&test_dir)?;
+
+        let mut disk_use = DiskUse::new(&cache_file);
+        assert_eq!(disk_use.scan(&test_dir)?, 71);
+
+        let filtered = ScanOptions {
+            exclude: vec!["subdir2/".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(disk_use.scan_with_options(&test_dir, &filtered)?, 42);
+
+        // Neither result overwrote nor leaked into the other
+        assert_eq!(disk_use.scan(&test_dir)?, 71);
+        assert_eq!(disk_use.get_stats(&test_dir).unwrap().total_size(), 71);
+        assert_eq!(
+            disk_use
+                .get_stats_with_options(&test_dir, &filtered)
+                .unwrap()
+                .total_size(),
+            42
+        );
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_allocated_size_mode() -> io::Result<()> {
+        use crate::scanner::SizeMode;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        let cache_file = temp_dir.path().join("cache.bin");
+
+        fs::create_dir(&test_dir)?;
+        // Sparse file: 8 MiB apparent size with no data blocks written
+        fs::File::create(test_dir.join("sparse.bin"))?.set_len(8 * 1024 * 1024)?;
+
+        let mut disk_use = DiskUse::new(&cache_file);
+        let apparent = disk_use.scan(&test_dir)?;
+        assert_eq!(apparent, 8 * 1024 * 1024);
+
+        let options = ScanOptions {
+            size_mode: SizeMode::Allocated,
+            ..Default::default()
+        };
+        let allocated = disk_use.scan_with_options(&test_dir, &options)?;
+        assert!(allocated < apparent);
+
+        // Both figures are kept in the cached stats
+        let stats = disk_use.get_stats(&test_dir).unwrap();
+        assert_eq!(stats.total_size(), apparent);
+        assert_eq!(stats.allocated_size(), allocated);
+
+        Ok(())
+    }
+
     #[test]
     fn test_cache_management() -> io::Result<()> {
         let temp_dir = TempDir::new()?;
@@ -218,4 +645,88 @@ mod tests {
 
         Ok(())
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_strict_scan_reports_partial_result() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        let cache_file = temp_dir.path().join("cache.bin");
+
+        fs::create_dir(&test_dir)?;
+        create_test_directory_structure(&test_dir)?;
+
+        let locked = test_dir.join("subdir1");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000))?;
+        if fs::read_dir(&locked).is_ok() {
+            // Running with privileges that bypass permission checks
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+            return Ok(());
+        }
+
+        let mut disk_use = DiskUse::new(&cache_file);
+        let options = ScanOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let result = disk_use.scan_with_options(&test_dir, &options);
+        let lenient = disk_use.scan(&test_dir);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+
+        // 71 bytes minus the 19 in the unreadable subdir1
+        match result {
+            Err(Error::PartialScan { size, errors, .. }) => {
+                assert_eq!(size, 52);
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].kind(), io::ErrorKind::PermissionDenied);
+            }
+            other => panic!("expected a partial scan, got {:?}", other),
+        }
+        assert_eq!(lenient?, 52);
+        assert_eq!(disk_use.last_scan_errors().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_root_is_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut disk_use = DiskUse::new(temp_dir.path().join("cache.bin"));
+        let missing = temp_dir.path().join("missing");
+
+        assert!(matches!(
+            disk_use.scan(&missing),
+            Err(Error::RootNotFound(path)) if path == missing
+        ));
+    }
+
+    #[test]
+    fn test_cancelled_scan_is_cached() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_directory_structure(&test_dir)?;
+
+        let mut disk_use = DiskUse::new(temp_dir.path().join("cache.bin"));
+        let token = crate::CancelToken::new();
+        token.cancel();
+        let options = ScanOptions {
+            cancel: Some(token),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            disk_use.scan_with_options(&test_dir, &options),
+            Err(Error::Cancelled { size: 0, .. })
+        ));
+        assert!(disk_use.get_stats(&test_dir).unwrap().is_incomplete());
+
+        // The incomplete entry is not reused as is
+        assert_eq!(disk_use.scan(&test_dir)?, 71);
+        assert!(!disk_use.get_stats(&test_dir).unwrap().is_incomplete());
+
+        Ok(())
+    }
 }
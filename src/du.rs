@@ -0,0 +1,408 @@
+//! Output compatible with GNU `du`
+//!
+//! Lines have the same `SIZE<TAB>PATH` layout and sizes as `du`, including the
+//! space taken by directory entries themselves, and are printed in the same
+//! order: each directory after its contents, and siblings in the order the
+//! directory lists them, which is read from disk for every printed directory.
+//!
+//! As in `du`, a hard-linked file is counted in the first directory it is
+//! found in, and a directory reached again through a later argument is
+//! skipped, so every file counts once across all arguments of a run.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::scanner::{DirStat, HardLink, SizeMode};
+
+/// Unit in which sizes are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuUnit {
+    /// Whole blocks of this many bytes, rounded up (`-b`, `-k`, `-m`)
+    Blocks(u64),
+    /// Powers of 1024 with a unit suffix, like `-h`
+    Human,
+}
+
+impl Default for DuUnit {
+    fn default() -> Self {
+        DuUnit::Blocks(1024)
+    }
+}
+
+/// Which lines are printed and how sizes are measured
+#[derive(Debug, Clone, Default)]
+pub struct DuOptions {
+    /// Print a line for every file, not just directories (`-a`)
+    pub all: bool,
+    /// Only print entries this many levels below an argument (`-d`; `-s` is 0)
+    pub max_depth: Option<usize>,
+    /// Measure apparent sizes instead of disk usage (`--apparent-size`, `-b`)
+    pub apparent_size: bool,
+    /// Unit of printed sizes
+    pub unit: DuUnit,
+}
+
+/// Files and directories already counted by the arguments printed so far
+#[derive(Debug, Default)]
+pub struct Seen {
+    /// Files by device and inode: hard-linked ones and the file arguments
+    files: HashSet<(u64, u64)>,
+    /// Directories by canonical path, as the trees are scanned under them
+    dirs: HashSet<PathBuf>,
+}
+
+impl DuOptions {
+    fn mode(&self) -> SizeMode {
+        if self.apparent_size {
+            SizeMode::Apparent
+        } else {
+            SizeMode::Allocated
+        }
+    }
+
+    /// Format a size in bytes in the configured unit
+    pub fn format_size(&self, bytes: u64) -> String {
+        match self.unit {
+            DuUnit::Blocks(block) => bytes.div_ceil(block).to_string(),
+            DuUnit::Human => human_size(bytes),
+        }
+    }
+
+    /// Size of a single file, symlink or other non-directory entry
+    pub fn file_size(&self, meta: &fs::Metadata) -> u64 {
+        match self.mode() {
+            SizeMode::Apparent => meta.len(),
+            SizeMode::Allocated => allocated_size(meta),
+        }
+    }
+
+    /// Write the line for a file argument and return its size in bytes
+    ///
+    /// A file already counted, on its own or inside a directory argument, is
+    /// not printed and adds nothing.
+    pub fn write_file(
+        &self,
+        out: &mut impl Write,
+        meta: &fs::Metadata,
+        display: &str,
+        seen: &mut Seen,
+    ) -> io::Result<u64> {
+        let parent = match Path::new(display).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let in_seen_dir = parent
+            .canonicalize()
+            .is_ok_and(|parent| seen.dirs.contains(&parent));
+        if in_seen_dir || file_id(meta).is_some_and(|id| !seen.files.insert(id)) {
+            return Ok(0);
+        }
+
+        let size = self.file_size(meta);
+        writeln!(out, "{}\t{}", self.format_size(size), display)?;
+        Ok(size)
+    }
+
+    /// Write the lines for a scanned directory and return its total size in bytes
+    ///
+    /// Paths are printed starting with `display`, the argument as the user
+    /// typed it, rather than the canonical path the tree was scanned under.
+    /// Each directory with printed entries is listed from disk to order them,
+    /// and with `all` to find its files, as the cache only keeps
+    /// per-directory totals. A directory already counted is not printed and
+    /// adds nothing.
+    pub fn write_tree(
+        &self,
+        out: &mut impl Write,
+        stat: &DirStat,
+        display: &str,
+        seen: &mut Seen,
+    ) -> io::Result<u64> {
+        if !seen.dirs.insert(stat.path().to_path_buf()) {
+            return Ok(0);
+        }
+
+        // `du` keeps one trailing slash on the argument and none before child names
+        let trimmed = display.trim_end_matches('/');
+        let (root, prefix) = if trimmed.is_empty() {
+            ("/".to_string(), "/".to_string())
+        } else if trimmed.len() < display.len() {
+            (format!("{}/", trimmed), format!("{}/", trimmed))
+        } else {
+            (display.to_string(), format!("{}/", display))
+        };
+
+        let mut printer = Printer {
+            options: self,
+            out,
+            root: stat.path(),
+            root_display: root,
+            prefix,
+            seen,
+        };
+        printer.visit(stat, 0)
+    }
+}
+
+/// State for printing one argument's tree
+struct Printer<'a, W> {
+    options: &'a DuOptions,
+    out: &'a mut W,
+    root: &'a Path,
+    root_display: String,
+    prefix: String,
+    seen: &'a mut Seen,
+}
+
+impl<W: Write> Printer<'_, W> {
+    /// Print a directory after its contents, returning its size including directory entries
+    fn visit(&mut self, stat: &DirStat, depth: usize) -> io::Result<u64> {
+        let mode = self.options.mode();
+        let listed = |depth| self.options.max_depth.is_none_or(|max| depth <= max);
+
+        // Files with a single link, directly in this directory
+        let children_unique: u64 = stat.children.values().map(|c| c.unique_size(mode)).sum();
+        let mut size = stat.own_size(mode) + stat.unique_size(mode).saturating_sub(children_unique);
+
+        // Below the deepest printed level only the total matters, not the order
+        if !listed(depth + 1) {
+            for link in &stat.hard_links {
+                size += self.count_link(link).unwrap_or(0);
+            }
+            for child in stat.children_by_name() {
+                size += self.visit_child(child, depth + 1)?;
+            }
+            return self.print_dir(stat, depth, size);
+        }
+
+        // A hard-linked file counts where it is first found, so links are counted in listing order
+        let links: HashMap<(u64, u64), &HardLink> = stat
+            .hard_links
+            .iter()
+            .map(|link| ((link.dev, link.ino), link))
+            .collect();
+        for entry in listing(stat) {
+            match entry {
+                Listed::Dir(child) => size += self.visit_child(child, depth + 1)?,
+                Listed::File(path, meta) => {
+                    let first = match hard_link_id(&meta) {
+                        Some(id) => match links.get(&id).and_then(|link| self.count_link(link)) {
+                            Some(link_size) => {
+                                size += link_size;
+                                true
+                            }
+                            None => false,
+                        },
+                        None => true,
+                    };
+                    // Of the names of a hard-linked file, only the one counted is printed
+                    if first && self.options.all {
+                        let file_size = self.options.file_size(&meta);
+                        let path = self.display(&path);
+                        writeln!(
+                            self.out,
+                            "{}\t{}",
+                            self.options.format_size(file_size),
+                            path
+                        )?;
+                    }
+                }
+            }
+        }
+        self.print_dir(stat, depth, size)
+    }
+
+    /// Visit a subdirectory unless it is a skipped mount point or was already counted
+    fn visit_child(&mut self, child: &DirStat, depth: usize) -> io::Result<u64> {
+        if !child.is_mount_point() && self.seen.dirs.insert(child.path().to_path_buf()) {
+            self.visit(child, depth)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Count a hard-linked file, returning its size unless it was already counted
+    fn count_link(&mut self, link: &HardLink) -> Option<u64> {
+        self.seen
+            .files
+            .insert((link.dev, link.ino))
+            .then(|| match self.options.mode() {
+                SizeMode::Apparent => link.size,
+                SizeMode::Allocated => link.allocated,
+            })
+    }
+
+    /// Print a directory's line if it is within the maximum depth, and return its size
+    fn print_dir(&mut self, stat: &DirStat, depth: usize, size: u64) -> io::Result<u64> {
+        if self.options.max_depth.is_none_or(|max| depth <= max) {
+            let path = self.display(stat.path());
+            writeln!(self.out, "{}\t{}", self.options.format_size(size), path)?;
+        }
+        Ok(size)
+    }
+
+    fn display(&self, path: &Path) -> String {
+        match path.strip_prefix(self.root) {
+            Ok(relative) if relative.as_os_str().is_empty() => self.root_display.clone(),
+            Ok(relative) => format!("{}{}", self.prefix, relative.display()),
+            Err(_) => path.display().to_string(),
+        }
+    }
+}
+
+/// An entry of a directory being printed
+enum Listed<'a> {
+    /// A subdirectory, with its cached tree
+    Dir(&'a DirStat),
+    /// Any other entry, with its metadata
+    File(PathBuf, fs::Metadata),
+}
+
+/// The entries of a directory in the order `du` visits them
+///
+/// `du` walks each directory in the order it is read, so it is read again
+/// here. Subdirectories are taken from the cached tree, and any not found on
+/// disk any more come last, by name. A directory that cannot be read gives
+/// just its cached subdirectories.
+fn listing(stat: &DirStat) -> Vec<Listed<'_>> {
+    let mut entries = Vec::new();
+    let mut found = HashSet::new();
+    for entry in fs::read_dir(stat.path()).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if let Some(child) = stat.children.get(&path) {
+            found.insert(child.path());
+            entries.push(Listed::Dir(child));
+        } else if let Ok(meta) = path.symlink_metadata() {
+            if !meta.is_dir() {
+                entries.push(Listed::File(path, meta));
+            }
+        }
+    }
+    entries.extend(
+        stat.children_by_name()
+            .into_iter()
+            .filter(|child| !found.contains(child.path()))
+            .map(Listed::Dir),
+    );
+    entries
+}
+
+/// Format a size like `du -h`: powers of 1024 rounded up, with one decimal below 10
+pub fn human_size(bytes: u64) -> String {
+    const BASE: u64 = 1024;
+    const UNITS: [&str; 9] = ["", "K", "M", "G", "T", "P", "E", "Z", "Y"];
+
+    if bytes < BASE {
+        return bytes.to_string();
+    }
+
+    // Scale down, tracking the tenths digit and whether anything below it was dropped
+    let mut amount = bytes;
+    let mut tenths = 0;
+    let mut remainder = false;
+    let mut exponent = 0;
+    while amount >= BASE && exponent < UNITS.len() - 1 {
+        let r10 = (amount % BASE) * 10 + tenths;
+        remainder |= !r10.is_multiple_of(BASE);
+        amount /= BASE;
+        tenths = r10 / BASE;
+        exponent += 1;
+    }
+
+    if amount < 10 {
+        if remainder {
+            tenths += 1;
+            remainder = false;
+            if tenths == 10 {
+                amount += 1;
+                tenths = 0;
+            }
+        }
+        if amount < 10 {
+            return format!("{}.{}{}", amount, tenths, UNITS[exponent]);
+        }
+    }
+
+    if remainder || tenths > 0 {
+        amount += 1;
+        if amount == BASE && exponent < UNITS.len() - 1 {
+            return format!("1.0{}", UNITS[exponent + 1]);
+        }
+    }
+    format!("{}{}", amount, UNITS[exponent])
+}
+
+/// Space allocated on disk for a file
+#[cfg(unix)]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.blocks() * 512
+}
+
+/// Space allocated on disk for a file (block counts are unavailable, so use length)
+#[cfg(not(unix))]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    meta.len()
+}
+
+/// Identity of a file with other hard links
+#[cfg(unix)]
+fn hard_link_id(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (meta.nlink() > 1).then(|| (meta.dev(), meta.ino()))
+}
+
+/// Identity of a file with other hard links (not tracked on this platform)
+#[cfg(not(unix))]
+fn hard_link_id(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Identity of any file
+#[cfg(unix)]
+fn file_id(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Identity of any file (not tracked on this platform)
+#[cfg(not(unix))]
+fn file_id(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_human_size_matches_du() {
+        assert_eq!(human_size(0), "0");
+        assert_eq!(human_size(1023), "1023");
+        assert_eq!(human_size(1024), "1.0K");
+        assert_eq!(human_size(1025), "1.1K");
+        assert_eq!(human_size(4096), "4.0K");
+        assert_eq!(human_size(10 * 1024 - 1), "10K");
+        assert_eq!(human_size(10 * 1024 + 1), "11K");
+        assert_eq!(human_size(1024 * 1024 - 1), "1.0M");
+        assert_eq!(human_size(98 * 1024 * 1024), "98M");
+        assert_eq!(human_size(1536 * 1024 * 1024), "1.5G");
+    }
+
+    #[test]
+    fn test_block_units_round_up() {
+        let options = DuOptions::default();
+        assert_eq!(options.format_size(0), "0");
+        assert_eq!(options.format_size(1), "1");
+        assert_eq!(options.format_size(4096), "4");
+        let mebibytes = DuOptions {
+            unit: DuUnit::Blocks(1024 * 1024),
+            ..Default::default()
+        };
+        assert_eq!(mebibytes.format_size(1024 * 1024 + 1), "2");
+    }
+}
@@ -0,0 +1,147 @@
+//! Error type shared by the cache, scanner and high-level API
+
+use std::{io, path::PathBuf};
+
+use crate::scanner::ScanError;
+
+/// Result type used throughout the library
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors returned by the library
+///
+/// Every variant keeps the underlying cause reachable through
+/// `std::error::Error::source`. Converting into `io::Error` preserves the
+/// closest matching `io::ErrorKind` for callers that still work with I/O errors.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The cache file exists but could not be read
+    #[error("failed to load cache from '{}'", path.display())]
+    CacheLoad {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The cache file could not be written or removed
+    #[error("failed to save cache to '{}'", path.display())]
+    CacheSave {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The cache file was read but its contents could not be decoded
+    #[error("cache '{}' is corrupt or in an unknown format", path.display())]
+    CacheFormat {
+        path: PathBuf,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// The cache file was written by an incompatible version of the format
+    #[error(
+        "cache '{}' has format version {found}, expected {expected}",
+        path.display()
+    )]
+    CacheVersion {
+        path: PathBuf,
+        found: u32,
+        expected: u32,
+    },
+
+    /// The path to scan does not exist
+    #[error("'{}' does not exist", .0.display())]
+    RootNotFound(PathBuf),
+
+    /// The path to scan is not a directory
+    #[error("'{}' is not a directory", .0.display())]
+    NotADirectory(PathBuf),
+
+    /// The path to scan exists but could not be read
+    #[error("cannot read '{}'", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// An include or exclude pattern is not a valid glob
+    #[error("invalid pattern '{pattern}'")]
+    InvalidPattern {
+        pattern: String,
+        #[source]
+        source: globset::Error,
+    },
+
+    /// Some entries below the scanned path could not be read
+    ///
+    /// Only returned when `ScanOptions::strict` is set; the partial result is
+    /// still cached, and its total is carried here.
+    #[error(
+        "scan of '{}' is incomplete: {} entries could not be read",
+        path.display(),
+        errors.len()
+    )]
+    PartialScan {
+        path: PathBuf,
+        size: u64,
+        errors: Vec<ScanError>,
+    },
+
+    /// The dedicated scanner thread pool could not be started
+    #[error("failed to start scanner threads")]
+    ThreadPool(#[source] rayon::ThreadPoolBuildError),
+
+    /// The scan was cancelled or ran out of time before it completed
+    ///
+    /// The partial result is still cached, and its total is carried here.
+    #[error("scan of '{}' was cancelled", path.display())]
+    Cancelled { path: PathBuf, size: u64 },
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match &err {
+            Error::CacheLoad { source, .. }
+            | Error::CacheSave { source, .. }
+            | Error::Io { source, .. } => source.kind(),
+            Error::CacheFormat { .. } | Error::CacheVersion { .. } => io::ErrorKind::InvalidData,
+            Error::RootNotFound(_) => io::ErrorKind::NotFound,
+            Error::NotADirectory(_) => io::ErrorKind::NotADirectory,
+            Error::InvalidPattern { .. } => io::ErrorKind::InvalidInput,
+            Error::PartialScan { .. } | Error::ThreadPool(_) => io::ErrorKind::Other,
+            Error::Cancelled { .. } => io::ErrorKind::Interrupted,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_source_chain_and_io_conversion() {
+        let err = Error::CacheSave {
+            path: PathBuf::from("/cache/cache.bin"),
+            source: io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+        };
+        assert_eq!(
+            err.to_string(),
+            "failed to save cache to '/cache/cache.bin'"
+        );
+        assert_eq!(err.source().unwrap().to_string(), "denied");
+
+        let io_err = io::Error::from(err);
+        assert_eq!(io_err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(matches!(
+            io_err.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+            Some(Error::CacheSave { .. })
+        ));
+
+        let missing = io::Error::from(Error::RootNotFound(PathBuf::from("/missing")));
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,203 @@
+//! Include/exclude glob filtering of scanned entries
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+/// Compiled set of glob patterns
+///
+/// Patterns follow `.gitignore` conventions: a trailing `/` matches directories
+/// only, a pattern containing `/` is matched against the path relative to the
+/// scan root, and any other pattern is matched against the entry's file name.
+struct PatternSet {
+    names: GlobSet,
+    paths: GlobSet,
+    dir_names: GlobSet,
+    dir_paths: GlobSet,
+}
+
+impl PatternSet {
+    fn new(patterns: &[String]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        let mut dir_names = GlobSetBuilder::new();
+        let mut dir_paths = GlobSetBuilder::new();
+
+        for pattern in patterns {
+            let dir_only = pattern.ends_with('/');
+            let trimmed = pattern.trim_end_matches('/');
+            let anchored = is_anchored(pattern);
+            let glob = GlobBuilder::new(trimmed.trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(invalid_pattern)?;
+
+            let builder = match (anchored, dir_only) {
+                (false, false) => &mut names,
+                (true, false) => &mut paths,
+                (false, true) => &mut dir_names,
+                (true, true) => &mut dir_paths,
+            };
+            builder.add(glob);
+        }
+
+        let build = |builder: GlobSetBuilder| builder.build().map_err(invalid_pattern);
+
+        Ok(Self {
+            names: build(names)?,
+            paths: build(paths)?,
+            dir_names: build(dir_names)?,
+            dir_paths: build(dir_paths)?,
+        })
+    }
+
+    /// Check whether an entry (given relative to the scan root) matches any pattern
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        let name = relative.file_name().map(Path::new).unwrap_or(relative);
+
+        self.names.is_match(name)
+            || self.paths.is_match(relative)
+            || (is_dir && (self.dir_names.is_match(name) || self.dir_paths.is_match(relative)))
+    }
+}
+
+/// Whether a pattern is matched relative to the scan root rather than against file names
+pub(crate) fn is_anchored(pattern: &str) -> bool {
+    pattern.trim_end_matches('/').contains('/')
+}
+
+fn invalid_pattern(source: globset::Error) -> Error {
+    Error::InvalidPattern {
+        pattern: source.glob().unwrap_or_default().to_string(),
+        source,
+    }
+}
+
+/// Include/exclude filter applied to every entry under the scan root
+pub(crate) struct Filter {
+    exclude: PatternSet,
+    include: Option<PatternSet>,
+}
+
+impl Filter {
+    /// Compile the given patterns, failing on invalid globs
+    pub(crate) fn new(exclude: &[String], include: &[String]) -> Result<Self> {
+        Ok(Self {
+            exclude: PatternSet::new(exclude)?,
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(PatternSet::new(include)?)
+            },
+        })
+    }
+
+    /// Whether an entry is excluded (excluded directories are not descended into)
+    pub(crate) fn excludes(&self, relative: &Path, is_dir: bool) -> bool {
+        self.exclude.matches(relative, is_dir)
+    }
+
+    /// Whether a file is counted; with no include patterns every file is
+    pub(crate) fn includes_file(&self, relative: &Path) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.matches(relative, false))
+    }
+}
+
+/// Fold bytes into an FNV-1a hash, which unlike the std hasher is stable across Rust releases
+pub(crate) fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Stable fingerprint of a filter configuration
+///
+/// Used to key cached results so that a tree computed under one filter is
+/// never served for a scan with another. An empty filter has fingerprint 0.
+pub(crate) fn fingerprint(exclude: &[String], include: &[String], respect_ignore: bool) -> u64 {
+    if exclude.is_empty() && include.is_empty() && !respect_ignore {
+        return 0;
+    }
+
+    let mut exclude = exclude.to_vec();
+    let mut include = include.to_vec();
+    exclude.sort();
+    include.sort();
+
+    let mut hash = fnv1a(0xcbf2_9ce4_8422_2325, &[u8::from(respect_ignore)]);
+    for (tag, patterns) in [(b'-', &exclude), (b'+', &include)] {
+        for pattern in patterns {
+            hash = fnv1a(hash, &[tag]);
+            hash = fnv1a(hash, pattern.as_bytes());
+            hash = fnv1a(hash, &[0]);
+        }
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(list: &[&str]) -> Vec<String> {
+        list.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_exclude_patterns() -> Result<()> {
+        let filter = Filter::new(&patterns(&["*.tmp", ".snapshot/", "data/scratch"]), &[])?;
+
+        // Name patterns match at any depth
+        assert!(filter.excludes(Path::new("a.tmp"), false));
+        assert!(filter.excludes(Path::new("x/y/a.tmp"), false));
+        assert!(!filter.excludes(Path::new("a.txt"), false));
+
+        // Trailing slash only matches directories
+        assert!(filter.excludes(Path::new("x/.snapshot"), true));
+        assert!(!filter.excludes(Path::new("x/.snapshot"), false));
+
+        // Patterns with a slash are anchored at the scan root
+        assert!(filter.excludes(Path::new("data/scratch"), true));
+        assert!(!filter.excludes(Path::new("other/data/scratch"), true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_patterns() -> Result<()> {
+        let filter = Filter::new(&[], &patterns(&["*.bin"]))?;
+        assert!(filter.includes_file(Path::new("model/weights.bin")));
+        assert!(!filter.includes_file(Path::new("model/notes.txt")));
+
+        let no_include = Filter::new(&[], &[])?;
+        assert!(no_include.includes_file(Path::new("anything")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_rejected() {
+        let err = Filter::new(&patterns(&["[unclosed"]), &[]).err().unwrap();
+        assert!(matches!(err, Error::InvalidPattern { pattern, .. } if pattern == "[unclosed"));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(fingerprint(&[], &[], false), 0);
+        assert_ne!(fingerprint(&[], &[], true), 0);
+        assert_eq!(
+            fingerprint(&patterns(&["a", "b"]), &[], false),
+            fingerprint(&patterns(&["b", "a"]), &[], false)
+        );
+        assert_ne!(
+            fingerprint(&patterns(&["a"]), &[], false),
+            fingerprint(&[], &patterns(&["a"]), false)
+        );
+    }
+}
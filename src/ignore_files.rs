@@ -0,0 +1,93 @@
+//! Hierarchical `.gitignore` / `.ignore` / `.acmeduignore` rules
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::{fs, path::Path};
+
+use crate::filter::fnv1a;
+
+/// Ignore files read in every directory, from lowest to highest precedence
+pub(crate) const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore", ".acmeduignore"];
+
+/// Ignore rules declared by the ignore files of a single directory
+pub(crate) struct DirRules {
+    matcher: Option<Gitignore>,
+    /// Hash of the rules in effect for this directory, including its ancestors'
+    pub(crate) hash: u64,
+}
+
+impl DirRules {
+    /// Rules for a directory whose ignore files are not read, keeping the parent's hash
+    pub(crate) fn inherited(parent_hash: u64) -> Self {
+        Self {
+            matcher: None,
+            hash: parent_hash,
+        }
+    }
+
+    /// Read the ignore files of `dir`, chaining the hash of the parent's rules
+    pub(crate) fn load(dir: &Path, parent_hash: u64) -> Self {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut hash = parent_hash;
+        let mut found = false;
+
+        for name in IGNORE_FILES {
+            let file = dir.join(name);
+            let Ok(contents) = fs::read_to_string(&file) else {
+                continue;
+            };
+            found = true;
+            hash = fnv1a(fnv1a(hash, name.as_bytes()), contents.as_bytes());
+            for line in contents.lines() {
+                // Invalid lines are skipped, as git does
+                let _ = builder.add_line(Some(file.clone()), line);
+            }
+        }
+
+        Self {
+            matcher: found.then(|| builder.build().ok()).flatten(),
+            hash,
+        }
+    }
+
+    /// Check a path against this directory's rules alone
+    ///
+    /// Returns `Some(true)` if ignored, `Some(false)` if explicitly re-included
+    /// with `!pattern`, and `None` if no rule here matches.
+    pub(crate) fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        match self.matcher.as_ref()?.matched(path, is_dir) {
+            Match::None => None,
+            Match::Ignore(_) => Some(true),
+            Match::Whitelist(_) => Some(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rules_and_precedence() -> std::io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        fs::write(dir.join(".gitignore"), "target/\n*.log\n")?;
+        fs::write(dir.join(".acmeduignore"), "!keep.log\n")?;
+
+        let rules = DirRules::load(dir, 0);
+        assert_eq!(rules.matched(&dir.join("target"), true), Some(true));
+        assert_eq!(rules.matched(&dir.join("target"), false), None);
+        assert_eq!(rules.matched(&dir.join("build.log"), false), Some(true));
+        assert_eq!(rules.matched(&dir.join("keep.log"), false), Some(false));
+        assert_eq!(rules.matched(&dir.join("src"), true), None);
+
+        // The hash changes with the rules and is chained from the parent
+        assert_ne!(rules.hash, 0);
+        assert_ne!(DirRules::load(dir, 1).hash, rules.hash);
+        fs::write(dir.join(".ignore"), "*.tmp\n")?;
+        assert_ne!(DirRules::load(dir, 0).hash, rules.hash);
+
+        Ok(())
+    }
+}
@@ -4,12 +4,29 @@
 //! designed for applications that work with mostly immutable files.
 
 mod cache;
+mod cache_archive;
+mod cache_format;
+mod cancel;
 mod disk_use;
+mod error;
+mod filter;
+mod ignore_files;
+mod progress;
+mod report;
 mod scanner;
+mod semaphore;
+mod summary;
+mod traverse;
 
 // Re-export public API
+pub use cancel::CancelToken;
 pub use disk_use::DiskUse;
-pub use scanner::DirStat;
+pub use error::{Error, Result};
+pub use progress::{Progress, ProgressObserver};
+pub use report::ScanReport;
+pub use scanner::{DirStat, Parallelism, ScanError, ScanOptions, SizeMode, SymlinkPolicy};
+pub use summary::ScanSummary;
+pub use traverse::{BreadthFirst, DepthFirst};
 
 use std::{env, path::PathBuf};
 
@@ -84,6 +101,10 @@ pub fn get_default_cache_path() -> PathBuf {
 /// Logger module for file-based logging
 pub mod logger;
 
+pub mod output;
+
+pub mod du;
+
 #[cfg(test)]
 mod tests {
     use super::*;
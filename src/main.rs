@@ -1,8 +1,17 @@
-use std::io;
-use std::path::Path;
+use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use acme_disk_use::{format_size, DiskUse};
-use clap::{Parser, Subcommand};
+use acme_disk_use::du::{DuOptions, DuUnit, Seen};
+use acme_disk_use::output::{JsonReport, Listing, NdjsonRecord, SortOrder, TableFormat};
+use acme_disk_use::{
+    format_size, CancelToken, DirStat, DiskUse, Parallelism, Progress, ProgressObserver,
+    ScanOptions, ScanReport, SizeMode, SymlinkPolicy,
+};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(name = "acme-disk-use")]
@@ -23,18 +32,461 @@ struct Cli {
     /// Ignore cache and scan fresh
     #[arg(long)]
     ignore_cache: bool,
+
+    /// Fail instead of starting over when the cache file cannot be loaded
+    #[arg(long, global = true)]
+    strict_cache: bool,
+
+    /// Which file size to report
+    #[arg(long, value_enum, default_value_t = SizeArg::Apparent)]
+    size_mode: SizeArg,
+
+    /// Count each hard-linked file once instead of once per link
+    #[arg(long)]
+    dedup_hard_links: bool,
+
+    /// Skip directories on different file systems
+    #[arg(short = 'x', long)]
+    one_file_system: bool,
+
+    /// When to follow symbolic links
+    #[arg(long, value_enum, default_value_t = SymlinkArg::RootOnly)]
+    symlinks: SymlinkArg,
+
+    /// Skip files and directories matching a glob pattern (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Read exclude patterns from a file, one per line
+    #[arg(long, value_name = "FILE")]
+    exclude_from: Vec<String>,
+
+    /// Only count files matching a glob pattern (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<String>,
+
+    /// Honour .gitignore, .ignore and .acmeduignore files, reporting ignored bytes separately
+    #[arg(long)]
+    respect_ignore_files: bool,
+
+    /// Number of scanner threads (defaults to one per CPU)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    threads: Option<u32>,
+
+    /// Minimum number of subdirectories before they are scanned in parallel
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    parallel_threshold: usize,
+
+    /// Maximum number of directories read at the same time
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_io: Option<u32>,
+
+    /// Stop scanning after this many seconds, caching the progress made so far
+    #[arg(long, value_name = "SECONDS")]
+    time_limit: Option<u64>,
+
+    /// Do not show a progress line (it is only shown when stderr is a terminal)
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Print cache and timing statistics for the scan to stderr
+    #[arg(long)]
+    stats: bool,
+
+    /// List every directory down to N levels below PATH with its size and file count
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Order of subdirectories in the --max-depth listing
+    #[arg(long, value_enum, default_value_t = SortArg::Size)]
+    sort: SortArg,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = FormatArg::Text)]
+    format: FormatArg,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SizeArg {
+    /// Logical file length (like `du --apparent-size`)
+    Apparent,
+    /// Blocks allocated on disk (like plain `du`)
+    Allocated,
+}
+
+impl From<SizeArg> for SizeMode {
+    fn from(arg: SizeArg) -> Self {
+        match arg {
+            SizeArg::Apparent => SizeMode::Apparent,
+            SizeArg::Allocated => SizeMode::Allocated,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SymlinkArg {
+    /// Count links themselves, never follow them (like `du -P`)
+    Never,
+    /// Follow all links, skipping cycles (like `du -L`)
+    Always,
+    /// Follow only a symlinked PATH argument (like `du -H`)
+    RootOnly,
+}
+
+impl From<SymlinkArg> for SymlinkPolicy {
+    fn from(arg: SymlinkArg) -> Self {
+        match arg {
+            SymlinkArg::Never => SymlinkPolicy::Never,
+            SymlinkArg::Always => SymlinkPolicy::Always,
+            SymlinkArg::RootOnly => SymlinkPolicy::RootOnly,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortArg {
+    /// Largest first
+    Size,
+    /// Alphabetically by path
+    Name,
+}
+
+impl From<SortArg> for SortOrder {
+    fn from(arg: SortArg) -> Self {
+        match arg {
+            SortArg::Size => SortOrder::Size,
+            SortArg::Name => SortOrder::Name,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FormatArg {
+    /// Human-readable summary line, or a listing with --max-depth
+    Text,
+    /// One JSON document, nesting subdirectories down to --max-depth
+    Json,
+    /// One JSON record per directory down to --max-depth, then a scan record
+    Ndjson,
+    /// Comma-separated row per directory, down to --max-depth if given
+    Csv,
+    /// Tab-separated row per directory, down to --max-depth if given
+    Tsv,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Clean the cache contents
     Clean,
+    /// Print disk usage with the flags and output layout of GNU du
+    Du(DuArgs),
 }
 
-fn main() -> io::Result<()> {
-    let cli = Cli::parse();
+/// The commonly used flags of GNU `du`
+///
+/// `-h` means human-readable sizes as in `du`, so help is only `--help`.
+#[derive(clap::Args)]
+#[command(disable_help_flag = true)]
+struct DuArgs {
+    /// Files and directories to measure (defaults to the current directory)
+    #[arg(value_name = "PATH")]
+    paths: Vec<String>,
+
+    /// Write counts for all files, not just directories
+    #[arg(short = 'a', long)]
+    all: bool,
+
+    /// Print apparent sizes rather than disk usage
+    #[arg(long)]
+    apparent_size: bool,
+
+    /// Equivalent to --apparent-size with sizes in bytes
+    #[arg(short = 'b', long, overrides_with = "bytes")]
+    bytes: bool,
+
+    /// Produce a grand total
+    #[arg(short = 'c', long)]
+    total: bool,
+
+    /// Print a total for a directory only if it is N or fewer levels below the argument
+    #[arg(short = 'd', long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Print sizes in human readable format (e.g., 1K 234M 2G)
+    #[arg(short = 'h', long, overrides_with = "human_readable")]
+    human_readable: bool,
+
+    /// Sizes in 1024-byte blocks (the default)
+    #[arg(short = 'k', overrides_with = "kibibytes")]
+    kibibytes: bool,
 
-    let mut disk_use = DiskUse::new_with_default_cache();
+    /// Sizes in 1024*1024-byte blocks
+    #[arg(short = 'm', overrides_with = "mebibytes")]
+    mebibytes: bool,
+
+    /// Display only a total for each argument
+    #[arg(short = 's', long, conflicts_with_all = ["all", "max_depth"])]
+    summarize: bool,
+
+    /// Skip directories on different file systems
+    #[arg(short = 'x', long)]
+    one_file_system: bool,
+
+    /// Ignore cache and scan fresh
+    #[arg(long)]
+    ignore_cache: bool,
+
+    /// Print help
+    #[arg(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+
+    /// Unit of the last of `-h`, `-k`, `-m` and `-b` on the command line
+    #[arg(skip)]
+    unit: Option<DuUnit>,
+}
+
+impl DuArgs {
+    /// Record which unit flag came last, as in `du` the last one wins
+    ///
+    /// The derived fields only say which flags were given, so their positions
+    /// are read from the matches.
+    fn set_unit(&mut self, matches: &ArgMatches) {
+        let units = [
+            ("human_readable", DuUnit::Human),
+            ("kibibytes", DuUnit::Blocks(1024)),
+            ("mebibytes", DuUnit::Blocks(1024 * 1024)),
+            ("bytes", DuUnit::Blocks(1)),
+        ];
+        self.unit = units
+            .into_iter()
+            .filter(|(id, _)| matches.value_source(id) == Some(ValueSource::CommandLine))
+            .filter_map(|(id, unit)| Some((matches.indices_of(id)?.max()?, unit)))
+            .max_by_key(|(index, _)| *index)
+            .map(|(_, unit)| unit);
+    }
+
+    fn options(&self) -> DuOptions {
+        DuOptions {
+            all: self.all,
+            max_depth: if self.summarize {
+                Some(0)
+            } else {
+                self.max_depth
+            },
+            apparent_size: self.apparent_size || self.bytes,
+            unit: self.unit.unwrap_or_default(),
+        }
+    }
+}
+
+/// Single status line on stderr, redrawn at most every 100 ms while scanning
+struct ProgressLine {
+    human_readable: bool,
+    last_draw: Mutex<Instant>,
+}
+
+impl ProgressLine {
+    const INTERVAL: Duration = Duration::from_millis(100);
+    const PATH_WIDTH: usize = 50;
+
+    fn new(human_readable: bool) -> Self {
+        Self {
+            human_readable,
+            last_draw: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Erase the progress line before printing results
+    fn clear() {
+        eprint!("\r\x1b[2K");
+    }
+}
+
+impl ProgressObserver for ProgressLine {
+    fn on_progress(&self, progress: &Progress) {
+        // Skip the update if another thread is drawing or one was drawn recently
+        let Ok(mut last_draw) = self.last_draw.try_lock() else {
+            return;
+        };
+        if last_draw.elapsed() < Self::INTERVAL {
+            return;
+        }
+        *last_draw = Instant::now();
+
+        // Keep the end of long paths, which is the informative part
+        let path = progress.current_path.display().to_string();
+        let len = path.chars().count();
+        let path = if len > Self::PATH_WIDTH {
+            let tail: String = path.chars().skip(len - Self::PATH_WIDTH + 1).collect();
+            format!("…{}", tail)
+        } else {
+            path
+        };
+
+        Self::clear();
+        eprint!(
+            "{} dirs scanned, {} reused, {} files, {}  {}",
+            progress.dirs_visited,
+            progress.cached_subtrees,
+            progress.files_counted,
+            format_size(progress.bytes_counted, self.human_readable),
+            path
+        );
+    }
+}
+
+/// Print a directory listing like `du -d N`, with sizes and file counts
+fn print_listing(listing: &Listing, root: &DirStat, human_readable: bool) {
+    for (_, stat) in listing.walk(root) {
+        println!(
+            "{:>10}  {:>8} files  {}",
+            format_size(listing.size(stat), human_readable),
+            stat.file_count(),
+            stat.path().display()
+        );
+    }
+}
+
+/// Run the `du` subcommand, returning whether every path could be measured in full
+fn run_du(disk_use: &mut DiskUse, args: &DuArgs) -> Result<bool, Box<dyn Error>> {
+    let du = args.options();
+    let options = ScanOptions {
+        ignore_cache: args.ignore_cache,
+        size_mode: if du.apparent_size {
+            SizeMode::Apparent
+        } else {
+            SizeMode::Allocated
+        },
+        dedup_hard_links: true,
+        one_file_system: args.one_file_system,
+        symlinks: SymlinkPolicy::Never,
+        ..Default::default()
+    };
+
+    let default_path = [String::from(".")];
+    let paths = if args.paths.is_empty() {
+        &default_path[..]
+    } else {
+        &args.paths[..]
+    };
+
+    let mut out = io::stdout().lock();
+    let mut complete = true;
+    let mut total = 0;
+    let mut seen = Seen::default();
+    for path in paths {
+        // Like du without -L, a symlink argument is measured as the link itself
+        let meta = match fs::symlink_metadata(path) {
+            Ok(meta) => meta,
+            Err(err) => {
+                eprintln!("acme-disk-use: cannot access '{}': {}", path, err);
+                complete = false;
+                continue;
+            }
+        };
+        if !meta.is_dir() {
+            total += du.write_file(&mut out, &meta, path, &mut seen)?;
+            continue;
+        }
+
+        let summary = match disk_use.scan_summary(path, &options) {
+            Ok(summary) => summary,
+            Err(err) => {
+                eprintln!("acme-disk-use: {}: {}", path, err);
+                complete = false;
+                continue;
+            }
+        };
+        total += du.write_tree(&mut out, summary.stats(), path, &mut seen)?;
+        for error in summary.errors() {
+            eprintln!("acme-disk-use: {}", error);
+            complete = false;
+        }
+    }
+
+    if args.total {
+        writeln!(out, "{}\ttotal", du.format_size(total))?;
+    }
+    if !args.ignore_cache {
+        disk_use.save_cache()?;
+    }
+    Ok(complete)
+}
+
+/// Print how much of the scan was served from the cache and where the time went
+fn print_report(report: &ScanReport) {
+    eprintln!("Scan statistics:");
+    eprintln!("  directories reused from cache: {}", report.dirs_reused);
+    eprintln!("  directories scanned:           {}", report.dirs_scanned);
+    eprintln!("  directories pruned:            {}", report.dirs_pruned);
+    eprintln!("  stat calls:                    {}", report.stat_calls);
+    eprintln!(
+        "  cache load time:               {:?}",
+        report.cache_load_time
+    );
+    eprintln!("  scan time:                     {:?}", report.scan_time);
+    eprintln!(
+        "  cache save time:               {:?}",
+        report.cache_save_time
+    );
+}
+
+/// Read glob patterns from a file, skipping blank lines and `#` comments
+fn read_patterns(path: &str) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Print the causes of an error already printed, one per line
+fn print_causes(err: &dyn Error) {
+    let mut source = err.source();
+    while let Some(cause) = source {
+        eprintln!("  caused by: {}", cause);
+        source = cause.source();
+    }
+}
+
+/// Load the default cache, warning and starting over if it cannot be loaded unless `strict`
+fn open_cache(strict: bool) -> Result<DiskUse, Box<dyn Error>> {
+    let cache_path = acme_disk_use::get_default_cache_path();
+    match DiskUse::open(&cache_path) {
+        Ok(disk_use) => Ok(disk_use),
+        Err(err) if strict => Err(err.into()),
+        Err(err) => {
+            eprintln!("acme-disk-use: warning: {}", err);
+            print_causes(&err);
+            eprintln!("acme-disk-use: warning: starting with an empty cache (use --strict-cache to stop instead)");
+            Ok(DiskUse::new(&cache_path))
+        }
+    }
+}
+
+fn main() {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let (Some(Commands::Du(args)), Some(("du", du_matches))) =
+        (&mut cli.command, matches.subcommand())
+    {
+        args.set_unit(du_matches);
+    }
+
+    if let Err(err) = run(cli) {
+        eprintln!("acme-disk-use: {}", err);
+        print_causes(err.as_ref());
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    // Clearing an unreadable cache is no reason to warn or stop
+    let mut disk_use = match cli.command {
+        Some(Commands::Clean) => DiskUse::new_with_default_cache(),
+        _ => open_cache(cli.strict_cache)?,
+    };
 
     match cli.command {
         Some(Commands::Clean) => {
@@ -42,32 +494,143 @@ fn main() -> io::Result<()> {
             println!("Cache cleared successfully.");
             return Ok(());
         }
+        Some(Commands::Du(args)) => {
+            if !run_du(&mut disk_use, &args)? {
+                std::process::exit(1);
+            }
+        }
         None => {
             // Default scan command
             let path = cli.path.as_deref().unwrap_or(".");
 
-            if !Path::new(path).exists() {
-                eprintln!("Error: Path '{}' does not exist", path);
-                std::process::exit(1);
+            let mut exclude = cli.exclude;
+            for file in &cli.exclude_from {
+                exclude.extend(read_patterns(file)?);
             }
 
-            // Scan the directory with appropriate options
-            let total_size = disk_use.scan_with_options(path, cli.ignore_cache)?;
+            let show_progress = !cli.no_progress && io::stderr().is_terminal();
+            if show_progress {
+                disk_use.set_progress_observer(ProgressLine::new(!cli.non_human_readable));
+            }
+
+            // Ctrl-C stops the scan but keeps its progress; a second Ctrl-C exits at once
+            let cancel = CancelToken::new();
+            let handler_token = cancel.clone();
+            ctrlc::set_handler(move || {
+                if handler_token.is_cancelled() {
+                    std::process::exit(130);
+                }
+                handler_token.cancel();
+            })?;
 
-            // Get file count using the same ignore_cache setting
-            let file_count = disk_use.get_file_count(path, cli.ignore_cache)?;
+            // Scan the directory with appropriate options
+            let options = ScanOptions {
+                ignore_cache: cli.ignore_cache,
+                size_mode: cli.size_mode.into(),
+                dedup_hard_links: cli.dedup_hard_links,
+                one_file_system: cli.one_file_system,
+                symlinks: cli.symlinks.into(),
+                exclude,
+                include: cli.include,
+                respect_ignore_files: cli.respect_ignore_files,
+                strict: false,
+                parallelism: Parallelism {
+                    threads: cli.threads.map(|n| n as usize),
+                    parallel_threshold: cli.parallel_threshold,
+                    max_concurrent_io: cli.max_io.map(|n| n as usize),
+                },
+                cancel: Some(cancel.clone()),
+                time_budget: cli.time_limit.map(Duration::from_secs),
+            };
+            let result = disk_use.scan_summary(path, &options);
+            if show_progress {
+                ProgressLine::clear();
+            }
+            let summary = match result {
+                Ok(summary) => summary,
+                Err(acme_disk_use::Error::Cancelled { size, .. }) => {
+                    eprintln!(
+                        "acme-disk-use: scan stopped early, partial total size: {}",
+                        format_size(size, !cli.non_human_readable)
+                    );
+                    // Completed subtrees are cached so the next run resumes from them
+                    if !cli.ignore_cache {
+                        disk_use.save_cache()?;
+                    }
+                    std::process::exit(if cancel.is_cancelled() { 130 } else { 1 });
+                }
+                Err(err) => return Err(err.into()),
+            };
 
             // Format output based on user preference
-            println!(
-                "Found {} files, total size: {}",
-                file_count,
-                format_size(total_size, !cli.non_human_readable)
-            );
+            // Tables flatten the whole tree unless told otherwise
+            let default_depth = match cli.format {
+                FormatArg::Csv | FormatArg::Tsv => usize::MAX,
+                _ => 0,
+            };
+            let listing = Listing {
+                max_depth: cli.max_depth.unwrap_or(default_depth),
+                sort: cli.sort.into(),
+                size_mode: options.size_mode,
+                dedup_hard_links: options.dedup_hard_links,
+            };
+            match cli.format {
+                FormatArg::Json => {
+                    let json = JsonReport::new(&summary, &listing);
+                    println!("{}", serde_json::to_string_pretty(&json)?);
+                }
+                FormatArg::Ndjson => {
+                    for line in NdjsonRecord::lines(&summary, &listing) {
+                        println!("{}", serde_json::to_string(&line)?);
+                    }
+                }
+                FormatArg::Csv | FormatArg::Tsv => {
+                    let table = if cli.format == FormatArg::Csv {
+                        TableFormat::Csv
+                    } else {
+                        TableFormat::Tsv
+                    };
+                    table.write(&mut io::stdout().lock(), summary.stats(), &listing)?;
+                }
+                FormatArg::Text if cli.max_depth.is_some() => {
+                    print_listing(&listing, summary.stats(), !cli.non_human_readable);
+                }
+                FormatArg::Text => {
+                    println!(
+                        "Found {} files, total size: {}",
+                        summary.file_count(),
+                        format_size(summary.size(), !cli.non_human_readable)
+                    );
+                }
+            }
+
+            if options.respect_ignore_files && cli.format == FormatArg::Text {
+                let ignored_size = summary.stats().ignored_size(options.size_mode);
+                println!(
+                    "Ignored by ignore files: {}",
+                    format_size(ignored_size, !cli.non_human_readable)
+                );
+            }
+
+            // Report unreadable entries like du does
+            let errors = summary.errors();
+            for error in errors {
+                eprintln!("acme-disk-use: {}", error);
+            }
+            let incomplete = !errors.is_empty();
 
             // Explicitly save cache before exiting (Drop will save too, but be explicit)
             if !cli.ignore_cache {
                 disk_use.save_cache()?;
             }
+
+            if cli.stats {
+                print_report(disk_use.last_scan_report());
+            }
+
+            if incomplete {
+                std::process::exit(1);
+            }
         }
     }
 
@@ -0,0 +1,454 @@
+//! Machine-readable scan output
+//!
+//! The records here define the JSON and NDJSON output of the command-line
+//! tool. Their layout is versioned by [`SCHEMA_VERSION`]: fields may be added
+//! within a version, but renaming, removing or changing the meaning of a field
+//! bumps it.
+//!
+//! `--format json` prints one [`JsonReport`] document. `--format ndjson`
+//! prints one [`NdjsonRecord`] per line: a `dir` record for every listed
+//! directory, parents before their subdirectories, followed by one `scan`
+//! record. `--format csv` and `--format tsv` print one [`TableFormat`] row per
+//! directory under a header row.
+
+use serde::Serialize;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::report::ScanReport;
+use crate::scanner::{DirStat, ScanError, SizeMode};
+use crate::summary::ScanSummary;
+
+/// Version of the JSON and NDJSON output layout
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Order of subdirectories in listings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Largest first, directories of equal size by path
+    #[default]
+    Size,
+    /// By path
+    Name,
+}
+
+/// Which directories a listing includes, in what order, and how they are sized
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Listing {
+    /// Deepest level listed below the scanned directory (0 lists only the directory itself)
+    pub max_depth: usize,
+    /// Order of subdirectories under their parent
+    pub sort: SortOrder,
+    /// Size reported as `size`
+    pub size_mode: SizeMode,
+    /// Whether `size` counts each hard-linked file once
+    pub dedup_hard_links: bool,
+}
+
+impl Listing {
+    /// Size of a directory as configured
+    pub fn size(&self, stat: &DirStat) -> u64 {
+        if self.dedup_hard_links {
+            stat.deduplicated_size(self.size_mode)
+        } else {
+            stat.size(self.size_mode)
+        }
+    }
+
+    /// Subdirectories of a directory in listing order, leaving out skipped mount points
+    pub fn children<'a>(&self, stat: &'a DirStat) -> Vec<&'a DirStat> {
+        // Sorting by name first leaves directories of equal size in name order
+        let mut children = stat.children_by_name();
+        children.retain(|child| !child.is_mount_point());
+        if self.sort == SortOrder::Size {
+            children.sort_by_key(|child| std::cmp::Reverse(self.size(child)));
+        }
+        children
+    }
+
+    /// Listed directories with their depth, each followed by its subdirectories
+    pub fn walk<'a>(&self, root: &'a DirStat) -> Vec<(usize, &'a DirStat)> {
+        let mut listed = Vec::new();
+        let mut stack = vec![(0, root)];
+        while let Some((depth, stat)) = stack.pop() {
+            listed.push((depth, stat));
+            if depth < self.max_depth {
+                let children = self.children(stat);
+                stack.extend(children.into_iter().rev().map(|child| (depth + 1, child)));
+            }
+        }
+        listed
+    }
+}
+
+/// A directory and its totals
+#[derive(Debug, Clone, Serialize)]
+pub struct DirRecord {
+    /// Path of the directory, as scanned (canonical unless it is an unfollowed symlink)
+    pub path: String,
+    /// Levels below the scanned directory, which has depth 0
+    pub depth: usize,
+    /// Size in the requested size mode, deduplicated if requested
+    pub size: u64,
+    /// Sum of file lengths
+    pub apparent_size: u64,
+    /// Space allocated on disk
+    pub allocated_size: u64,
+    /// Files counted
+    pub file_count: u64,
+    /// Directories, including this one
+    pub dir_count: u64,
+    /// Size of files matched by ignore files, which is not part of the totals
+    pub ignored_size: u64,
+    /// Whether some entry could not be read, leaving the totals short
+    pub incomplete: bool,
+    /// Listed subdirectories; only present in JSON output when listing below this depth
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<DirRecord>>,
+}
+
+impl DirRecord {
+    fn new(stat: &DirStat, depth: usize, listing: &Listing) -> Self {
+        Self {
+            path: stat.path().to_string_lossy().into_owned(),
+            depth,
+            size: listing.size(stat),
+            apparent_size: stat.size(SizeMode::Apparent),
+            allocated_size: stat.size(SizeMode::Allocated),
+            file_count: stat.file_count(),
+            dir_count: stat.dir_count(),
+            ignored_size: stat.ignored_size(listing.size_mode),
+            incomplete: stat.is_incomplete(),
+            children: None,
+        }
+    }
+
+    /// Record a directory with its subdirectories nested down to the listing's depth
+    fn tree(stat: &DirStat, depth: usize, listing: &Listing) -> Self {
+        let mut record = Self::new(stat, depth, listing);
+        if depth < listing.max_depth {
+            record.children = Some(
+                listing
+                    .children(stat)
+                    .into_iter()
+                    .map(|child| Self::tree(child, depth + 1, listing))
+                    .collect(),
+            );
+        }
+        record
+    }
+}
+
+/// How the scan used the cache and where the time went
+///
+/// The cache save time is not included, since output is written before saving.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanRecord {
+    /// Directories reused from the cache without being read
+    pub dirs_reused: u64,
+    /// Directories read from disk
+    pub dirs_scanned: u64,
+    /// Cached directories dropped because they were deleted
+    pub dirs_pruned: u64,
+    /// `stat`-family calls made
+    pub stat_calls: u64,
+    /// Seconds spent loading the cache
+    pub cache_load_secs: f64,
+    /// Seconds spent scanning, including cache validation
+    pub scan_secs: f64,
+}
+
+impl From<&ScanReport> for ScanRecord {
+    fn from(report: &ScanReport) -> Self {
+        Self {
+            dirs_reused: report.dirs_reused,
+            dirs_scanned: report.dirs_scanned,
+            dirs_pruned: report.dirs_pruned,
+            stat_calls: report.stat_calls,
+            cache_load_secs: report.cache_load_time.as_secs_f64(),
+            scan_secs: report.scan_time.as_secs_f64(),
+        }
+    }
+}
+
+/// An entry that could not be read
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorRecord {
+    /// Path of the entry
+    pub path: String,
+    /// Description of the failure
+    pub message: String,
+}
+
+impl From<&ScanError> for ErrorRecord {
+    fn from(error: &ScanError) -> Self {
+        Self {
+            path: error.path().to_string_lossy().into_owned(),
+            message: error.message().to_string(),
+        }
+    }
+}
+
+/// Document printed by `--format json`
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonReport {
+    /// Always [`SCHEMA_VERSION`]
+    pub schema_version: u32,
+    /// The scanned directory, with subdirectories nested down to the listing's depth
+    pub root: DirRecord,
+    /// Cache use and timings
+    pub scan: ScanRecord,
+    /// Entries that could not be read
+    pub errors: Vec<ErrorRecord>,
+}
+
+impl JsonReport {
+    /// Describe a scan, listing directories as configured
+    pub fn new(summary: &ScanSummary, listing: &Listing) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            root: DirRecord::tree(summary.stats(), 0, listing),
+            scan: summary.report().into(),
+            errors: summary.errors().iter().map(ErrorRecord::from).collect(),
+        }
+    }
+}
+
+/// One line of `--format ndjson` output
+#[derive(Debug, Clone, Serialize)]
+pub struct NdjsonRecord {
+    /// Always [`SCHEMA_VERSION`]
+    pub schema_version: u32,
+    /// The record itself, tagged by a `type` field
+    #[serde(flatten)]
+    pub record: NdjsonEntry,
+}
+
+/// Kinds of NDJSON records
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NdjsonEntry {
+    /// A listed directory, without nested children
+    Dir(DirRecord),
+    /// Last line: cache use, timings and unreadable entries
+    Scan {
+        #[serde(flatten)]
+        scan: ScanRecord,
+        errors: Vec<ErrorRecord>,
+    },
+}
+
+impl NdjsonRecord {
+    /// Describe a scan as one record per listed directory followed by a scan record
+    pub fn lines(summary: &ScanSummary, listing: &Listing) -> Vec<Self> {
+        let dirs = listing
+            .walk(summary.stats())
+            .into_iter()
+            .map(|(depth, stat)| NdjsonEntry::Dir(DirRecord::new(stat, depth, listing)));
+        let scan = NdjsonEntry::Scan {
+            scan: summary.report().into(),
+            errors: summary.errors().iter().map(ErrorRecord::from).collect(),
+        };
+
+        dirs.chain(std::iter::once(scan))
+            .map(|record| Self {
+                schema_version: SCHEMA_VERSION,
+                record,
+            })
+            .collect()
+    }
+}
+
+/// Delimited text with one row per directory, for spreadsheets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    /// Comma-separated, quoting fields that contain commas, quotes or line breaks
+    Csv,
+    /// Tab-separated, escaping tabs, line breaks and backslashes as `\t`, `\n`, `\r` and `\\`
+    Tsv,
+}
+
+impl TableFormat {
+    /// Column names of the header row
+    pub const COLUMNS: [&'static str; 8] = [
+        "path",
+        "depth",
+        "size",
+        "apparent_size",
+        "allocated_size",
+        "file_count",
+        "dir_count",
+        "last_scan",
+    ];
+
+    /// Write a header row and a row per listed directory, parents before their subdirectories
+    ///
+    /// `last_scan` is an RFC 3339 UTC timestamp of when the directory was last read.
+    pub fn write(&self, out: &mut impl Write, root: &DirStat, listing: &Listing) -> io::Result<()> {
+        self.write_row(out, &Self::COLUMNS.map(String::from))?;
+        for (depth, stat) in listing.walk(root) {
+            self.write_row(
+                out,
+                &[
+                    stat.path().to_string_lossy().into_owned(),
+                    depth.to_string(),
+                    listing.size(stat).to_string(),
+                    stat.size(SizeMode::Apparent).to_string(),
+                    stat.size(SizeMode::Allocated).to_string(),
+                    stat.file_count().to_string(),
+                    stat.dir_count().to_string(),
+                    rfc3339(stat.last_scan()),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&self, out: &mut impl Write, fields: &[String]) -> io::Result<()> {
+        let (separator, escaped): (&str, Vec<String>) = match self {
+            TableFormat::Csv => (",", fields.iter().map(|f| csv_field(f)).collect()),
+            TableFormat::Tsv => ("\t", fields.iter().map(|f| tsv_field(f)).collect()),
+        };
+        writeln!(out, "{}", escaped.join(separator))
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn tsv_field(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Format a time as an RFC 3339 UTC timestamp with second precision
+fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::scan_directory;
+    use std::borrow::Cow;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_json_and_ndjson_layout() -> crate::Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("root");
+        fs::create_dir_all(root.join("big/inner")).unwrap();
+        fs::create_dir_all(root.join("small")).unwrap();
+        fs::write(root.join("big/inner/data.bin"), [0u8; 100]).unwrap();
+        fs::write(root.join("small/a.txt"), "abc").unwrap();
+
+        let report = ScanReport::default();
+        let summary = ScanSummary::new(103, Cow::Owned(scan_directory(&root, None)?), &report, &[]);
+        let listing = Listing {
+            max_depth: 1,
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(JsonReport::new(&summary, &listing)).unwrap();
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["root"]["apparent_size"], 103);
+        assert_eq!(json["root"]["file_count"], 2);
+        assert_eq!(json["root"]["dir_count"], 4);
+        let children = json["root"]["children"].as_array().unwrap();
+        assert_eq!(children.len(), 2);
+        assert!(children[0]["path"].as_str().unwrap().ends_with("big"));
+        // Depth 1 is the deepest level listed, so it has no children field
+        assert!(children[0].get("children").is_none());
+        assert_eq!(json["scan"]["dirs_scanned"], 0);
+
+        let lines: Vec<serde_json::Value> = NdjsonRecord::lines(&summary, &listing)
+            .iter()
+            .map(|line| serde_json::to_value(line).unwrap())
+            .collect();
+        let types: Vec<&str> = lines.iter().map(|l| l["type"].as_str().unwrap()).collect();
+        assert_eq!(types, ["dir", "dir", "dir", "scan"]);
+        assert_eq!(lines[0]["depth"], 0);
+        assert_eq!(lines[1]["size"], 100);
+        assert_eq!(lines[2]["size"], 3);
+        assert!(lines.iter().all(|l| l["schema_version"] == SCHEMA_VERSION));
+        assert!(lines[3]["errors"].as_array().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_rows_and_escaping() -> crate::Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("root");
+        fs::create_dir_all(root.join("a,b/deep")).unwrap();
+        fs::write(root.join("a,b/deep/file"), "12345").unwrap();
+        let stats = scan_directory(&root, None)?;
+
+        let listing = Listing {
+            max_depth: usize::MAX,
+            ..Default::default()
+        };
+        let mut csv = Vec::new();
+        TableFormat::Csv.write(&mut csv, &stats, &listing).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0], TableFormat::COLUMNS.join(","));
+        assert!(rows[2].starts_with(&format!("\"{}\",1,5,5,", root.join("a,b").display())));
+        assert!(rows[3].contains(",2,5,5,"));
+
+        // Depth is limited like any other listing
+        let shallow = Listing {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let mut tsv = Vec::new();
+        TableFormat::Tsv.write(&mut tsv, &stats, &shallow).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert_eq!(tsv.lines().count(), 3);
+        assert!(tsv.lines().nth(2).unwrap().split('\t').nth(1) == Some("1"));
+
+        assert_eq!(tsv_field("a\tb\\c"), "a\\tb\\\\c");
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + std::time::Duration::from_secs(1_709_210_096)),
+            "2024-02-29T12:34:56Z"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! Progress reporting for long-running scans
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Snapshot of a scan's progress
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Directories read from disk so far
+    pub dirs_visited: u64,
+    /// Files counted so far, including those in reused cached subtrees
+    pub files_counted: u64,
+    /// Apparent size of the files counted so far
+    pub bytes_counted: u64,
+    /// Cached subtrees reused without being read again
+    pub cached_subtrees: u64,
+    /// Directory that was just finished
+    pub current_path: PathBuf,
+}
+
+/// Receives progress updates while a scan runs
+///
+/// `on_progress` is called from scanner threads after every directory, so it
+/// must be cheap; throttle any expensive output on the receiving side.
+/// Closures taking `&Progress` implement this trait.
+pub trait ProgressObserver: Send + Sync {
+    /// Called with the latest totals after a directory is finished
+    fn on_progress(&self, progress: &Progress);
+}
+
+impl<F: Fn(&Progress) + Send + Sync> ProgressObserver for F {
+    fn on_progress(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+/// Running counters shared by all scanner threads
+pub(crate) struct ProgressTracker<'a> {
+    observer: &'a dyn ProgressObserver,
+    dirs_visited: AtomicU64,
+    files_counted: AtomicU64,
+    bytes_counted: AtomicU64,
+    cached_subtrees: AtomicU64,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub(crate) fn new(observer: &'a dyn ProgressObserver) -> Self {
+        Self {
+            observer,
+            dirs_visited: AtomicU64::new(0),
+            files_counted: AtomicU64::new(0),
+            bytes_counted: AtomicU64::new(0),
+            cached_subtrees: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a directory read from disk with the files directly inside it
+    pub(crate) fn dir_scanned(&self, path: &Path, files: u64, bytes: u64) {
+        self.dirs_visited.fetch_add(1, Ordering::Relaxed);
+        self.add(path, files, bytes);
+    }
+
+    /// Record a cached subtree reused with all the files beneath it
+    pub(crate) fn subtree_reused(&self, path: &Path, files: u64, bytes: u64) {
+        self.cached_subtrees.fetch_add(1, Ordering::Relaxed);
+        self.add(path, files, bytes);
+    }
+
+    fn add(&self, path: &Path, files: u64, bytes: u64) {
+        self.files_counted.fetch_add(files, Ordering::Relaxed);
+        self.bytes_counted.fetch_add(bytes, Ordering::Relaxed);
+        self.observer.on_progress(&Progress {
+            dirs_visited: self.dirs_visited.load(Ordering::Relaxed),
+            files_counted: self.files_counted.load(Ordering::Relaxed),
+            bytes_counted: self.bytes_counted.load(Ordering::Relaxed),
+            cached_subtrees: self.cached_subtrees.load(Ordering::Relaxed),
+            current_path: path.to_path_buf(),
+        });
+    }
+}
@@ -0,0 +1,67 @@
+//! Statistics about how a scan used the cache
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Summary of the work done by a scan
+///
+/// Useful for checking that the cache is effective: a warm scan of an
+/// unchanged tree should reuse nearly every directory and make few stat calls.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanReport {
+    /// Directories whose cached results were reused without reading them again
+    pub dirs_reused: u64,
+    /// Directories read from disk, because they were new, changed or not cached
+    pub dirs_scanned: u64,
+    /// Cached directories dropped because they no longer exist
+    pub dirs_pruned: u64,
+    /// `stat`-family calls made while scanning and validating the cache
+    pub stat_calls: u64,
+    /// Time spent loading the cache file when the `DiskUse` was created
+    pub cache_load_time: Duration,
+    /// Time spent scanning, including cache validation
+    pub scan_time: Duration,
+    /// Time spent writing the cache file by the last save after this scan
+    pub cache_save_time: Duration,
+}
+
+/// Counters shared by all scanner threads
+#[derive(Default)]
+pub(crate) struct ScanCounters {
+    dirs_reused: AtomicU64,
+    dirs_scanned: AtomicU64,
+    dirs_pruned: AtomicU64,
+    stat_calls: AtomicU64,
+}
+
+impl ScanCounters {
+    pub(crate) fn dirs_reused(&self, count: u64) {
+        self.dirs_reused.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dir_scanned(&self) {
+        self.dirs_scanned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dirs_pruned(&self, count: u64) {
+        self.dirs_pruned.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stat(&self) {
+        self.stat_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Build the report for a scan that took `scan_time`
+    pub(crate) fn report(&self, scan_time: Duration) -> ScanReport {
+        ScanReport {
+            dirs_reused: self.dirs_reused.load(Ordering::Relaxed),
+            dirs_scanned: self.dirs_scanned.load(Ordering::Relaxed),
+            dirs_pruned: self.dirs_pruned.load(Ordering::Relaxed),
+            stat_calls: self.stat_calls.load(Ordering::Relaxed),
+            scan_time,
+            ..Default::default()
+        }
+    }
+}
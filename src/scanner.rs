@@ -1,21 +1,492 @@
 //! Directory scanning module for calculating disk usage statistics
 
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
+use crate::filter::{self, Filter};
+use crate::ignore_files::DirRules;
+use crate::progress::{ProgressObserver, ProgressTracker};
+use crate::report::{ScanCounters, ScanReport};
+use crate::semaphore::{Permit, Semaphore};
+use crate::traverse::{BreadthFirst, DepthFirst};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     fs, io,
     path::{Path, PathBuf},
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
 };
 
+/// Which size figure to report for files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeMode {
+    /// Logical file length (`st_size`), like `du --apparent-size`
+    #[default]
+    Apparent,
+    /// Space allocated on disk (`st_blocks * 512`), like plain `du`
+    Allocated,
+}
+
+/// How symbolic links encountered during a scan are treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Never follow symlinks, counting the size of each link itself (like `du -P`)
+    Never,
+    /// Follow symlinks everywhere, skipping links back to a directory being scanned (like `du -L`)
+    Always,
+    /// Follow the scan root if it is a symlink, but not links inside it (like `du -H`)
+    #[default]
+    RootOnly,
+}
+
+/// How much of the scan runs concurrently
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parallelism {
+    /// Number of scanner threads; `None` uses rayon's global pool (one per CPU)
+    pub threads: Option<usize>,
+    /// Minimum number of subdirectories before they are scanned in parallel
+    pub parallel_threshold: usize,
+    /// Maximum number of directories being read at the same time; `None` is unlimited
+    pub max_concurrent_io: Option<usize>,
+}
+
+impl Default for Parallelism {
+    fn default() -> Self {
+        Self {
+            threads: None,
+            parallel_threshold: 2,
+            max_concurrent_io: None,
+        }
+    }
+}
+
+/// Options controlling how a directory is scanned and reported
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Perform a fresh scan without reading or updating the cache
+    pub ignore_cache: bool,
+    /// Which size figure `DiskUse` reports
+    pub size_mode: SizeMode,
+    /// Count each hard-linked inode once per scan root instead of once per link
+    ///
+    /// Hard links are only tracked, and recorded in the cache, when this is set;
+    /// otherwise every file counts towards `unique_size`.
+    pub dedup_hard_links: bool,
+    /// Do not descend into directories on a different device than the scan root
+    pub one_file_system: bool,
+    /// Whether symlinks are followed or counted as links
+    pub symlinks: SymlinkPolicy,
+    /// Glob patterns for files and directories to leave out (see `--exclude`)
+    pub exclude: Vec<String>,
+    /// Glob patterns restricting which files are counted; empty counts all files
+    pub include: Vec<String>,
+    /// Honour `.gitignore`, `.ignore` and `.acmeduignore` files, tallying
+    /// ignored entries separately instead of in the regular totals
+    pub respect_ignore_files: bool,
+    /// Fail with `Error::PartialScan` if any entry could not be read
+    /// (the partial result is still cached)
+    pub strict: bool,
+    /// Thread pool size, parallelism threshold and I/O concurrency limit
+    pub parallelism: Parallelism,
+    /// Token to stop the scan early, keeping the subtrees completed so far
+    pub cancel: Option<CancelToken>,
+    /// Stop the scan once it has run this long, as if cancelled
+    pub time_budget: Option<Duration>,
+}
+
+impl ScanOptions {
+    /// Fingerprint of the options that change which entries a scan counts or records
+    ///
+    /// Cached trees are stored per fingerprint so a result computed under one
+    /// filter is never reused for a scan with a different filter, and a tree
+    /// without hard-link records never serves a deduplicating scan.
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let fingerprint =
+            filter::fingerprint(&self.exclude, &self.include, self.respect_ignore_files);
+        if self.dedup_hard_links {
+            filter::fnv1a(fingerprint, b"dedup-hard-links")
+        } else {
+            fingerprint
+        }
+    }
+
+    /// Whether a subdirectory scans the same on its own as within a scan of an ancestor
+    ///
+    /// Patterns containing `/` are matched relative to the scan root, and ignore
+    /// files in ancestors only apply when the scan starts above them, so either
+    /// makes a subtree's result depend on where the scan started.
+    pub(crate) fn is_root_independent(&self) -> bool {
+        !self.respect_ignore_files
+            && !self
+                .exclude
+                .iter()
+                .chain(&self.include)
+                .any(|pattern| filter::is_anchored(pattern))
+    }
+}
+
+/// State shared by every directory visited during one scan
+struct ScanContext<'a> {
+    options: &'a ScanOptions,
+    root: &'a Path,
+    root_dev: Option<u64>,
+    filter: Filter,
+    io_limit: Option<Semaphore>,
+    deadline: Option<Instant>,
+    progress: Option<ProgressTracker<'a>>,
+    counters: ScanCounters,
+}
+
+impl ScanContext<'_> {
+    /// Whether the scan was cancelled or ran out of time
+    fn cancelled(&self) -> bool {
+        self.options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Wait for a permit to read from the filesystem, if concurrent I/O is limited
+    fn io_permit(&self) -> Option<Permit<'_>> {
+        self.io_limit.as_ref().map(Semaphore::acquire)
+    }
+
+    /// Whether a directory with this metadata lies on another filesystem and must be skipped
+    fn crosses_mount(&self, meta: &fs::Metadata) -> bool {
+        self.options.one_file_system
+            && matches!((self.root_dev, device_id(meta)), (Some(root), Some(dev)) if root != dev)
+    }
+
+    /// Whether symlinks below the scan root are followed
+    fn follows_nested(&self) -> bool {
+        self.options.symlinks == SymlinkPolicy::Always
+    }
+
+    /// Running totals for a directory, tracking hard links only when they are deduplicated
+    fn totals(&self) -> Totals {
+        Totals {
+            track_links: self.options.dedup_hard_links,
+            ..Totals::default()
+        }
+    }
+
+    /// The hard-linked inodes of a cached subtree, if they are deduplicated
+    fn links(&self, stat: &DirStat) -> LinkSet {
+        let mut links = LinkSet::new();
+        if self.options.dedup_hard_links {
+            collect_links(stat, &mut links);
+        }
+        links
+    }
+
+    /// Classify a directory entry according to the active options
+    ///
+    /// Fails if the entry's metadata cannot be read.
+    fn classify(&self, entry: &fs::DirEntry, frame: &Frame) -> io::Result<Entry> {
+        let path = entry.path();
+        let resolved = self.resolve(entry, frame)?;
+        let filtered = self.apply_filter(&path, resolved);
+        Ok(self.apply_ignore_files(&path, filtered, frame))
+    }
+
+    /// Mark entries matched by ignore files so they are tallied separately
+    fn apply_ignore_files(&self, path: &Path, entry: Entry, frame: &Frame) -> Entry {
+        if !self.options.respect_ignore_files {
+            return entry;
+        }
+
+        match entry {
+            Entry::File(meta, via_link) if frame.is_ignored(path, false) => {
+                Entry::Ignored(meta, via_link)
+            }
+            Entry::Symlink(meta) if frame.is_ignored(path, false) => Entry::Ignored(meta, true),
+            Entry::Dir(dir) => Entry::Dir(DirEntry {
+                ignored: frame.is_ignored(path, true),
+                ..dir
+            }),
+            other => other,
+        }
+    }
+
+    /// Turn entries excluded by the include/exclude patterns into `Entry::Other`
+    fn apply_filter(&self, path: &Path, entry: Entry) -> Entry {
+        let relative = path.strip_prefix(self.root).unwrap_or(path);
+        let excluded = match &entry {
+            Entry::Dir(_) | Entry::MountPoint(_) => self.filter.excludes(relative, true),
+            Entry::File(..) | Entry::Symlink(_) | Entry::Ignored(..) => {
+                self.filter.excludes(relative, false) || !self.filter.includes_file(relative)
+            }
+            Entry::Other => false,
+        };
+
+        if excluded {
+            Entry::Other
+        } else {
+            entry
+        }
+    }
+
+    /// Resolve an entry's type, following symlinks as the policy allows
+    fn resolve(&self, entry: &fs::DirEntry, frame: &Frame) -> io::Result<Entry> {
+        let path = entry.path();
+        self.counters.stat();
+        let meta = entry.metadata()?;
+
+        if meta.file_type().is_symlink() {
+            if !self.follows_nested() {
+                return Ok(Entry::Symlink(meta));
+            }
+            // Broken links are counted as links rather than dropped
+            self.counters.stat();
+            let target = match fs::metadata(&path) {
+                Ok(target) => target,
+                Err(_) => return Ok(Entry::Symlink(meta)),
+            };
+            if target.is_dir() && frame.contains(dir_id(&path, &target).as_ref()) {
+                // Following would loop back into a directory already being scanned
+                return Ok(Entry::Symlink(meta));
+            }
+            return Ok(self.classify_resolved(path, target, true));
+        }
+
+        Ok(self.classify_resolved(path, meta, false))
+    }
+
+    /// Classify an entry whose metadata no longer refers to a symlink
+    fn classify_resolved(&self, path: PathBuf, meta: fs::Metadata, via_link: bool) -> Entry {
+        if meta.is_file() {
+            Entry::File(meta, via_link)
+        } else if meta.is_dir() {
+            if self.crosses_mount(&meta) {
+                Entry::MountPoint(path)
+            } else {
+                let id = if self.follows_nested() {
+                    dir_id(&path, &meta)
+                } else {
+                    None
+                };
+                Entry::Dir(DirEntry {
+                    path,
+                    meta,
+                    id,
+                    via_link,
+                    ignored: false,
+                })
+            }
+        } else {
+            Entry::Other
+        }
+    }
+}
+
+/// A directory entry as seen through the active scan options
+enum Entry {
+    /// Regular file (possibly reached through a followed symlink)
+    File(fs::Metadata, bool),
+    /// Symlink counted as the link itself
+    Symlink(fs::Metadata),
+    /// File or link matched by an ignore file (flag set if the entry is a symlink)
+    Ignored(fs::Metadata, bool),
+    /// Directory to descend into
+    Dir(DirEntry),
+    /// Directory on another filesystem, skipped by `one_file_system`
+    MountPoint(PathBuf),
+    /// Sockets, FIFOs, devices and excluded entries
+    Other,
+}
+
+/// Subdirectory found while listing a directory
+struct DirEntry {
+    path: PathBuf,
+    meta: fs::Metadata,
+    /// Identity used for cycle detection when symlinks are followed
+    id: Option<DirId>,
+    /// Reached by following a symlink
+    via_link: bool,
+    /// Matched by an ignore file (everything beneath it is ignored too)
+    ignored: bool,
+}
+
+/// Identity of a directory used for symlink cycle detection
+#[cfg(unix)]
+type DirId = (u64, u64);
+
+/// Identity of a directory used for symlink cycle detection
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(_path: &Path, meta: &fs::Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path, _meta: &fs::Metadata) -> Option<DirId> {
+    fs::canonicalize(path).ok()
+}
+
+/// Per-directory scan state, linked to the state of the parent directory
+struct Frame<'a> {
+    /// Identity used for symlink cycle detection (only when following links)
+    id: Option<DirId>,
+    /// Ignore rules declared in this directory
+    rules: DirRules,
+    /// Whether this whole directory is ignored by an ancestor's rules
+    ignored: bool,
+    /// Apparent and allocated size of the directory entry itself
+    own_size: (u64, u64),
+    parent: Option<&'a Frame<'a>>,
+}
+
+impl<'a> Frame<'a> {
+    /// State for the scan root
+    fn root(ctx: &ScanContext, path: &Path, meta: &fs::Metadata) -> Self {
+        Frame {
+            id: dir_id(path, meta).filter(|_| ctx.follows_nested()),
+            rules: Self::rules_for(ctx, path, 0, false),
+            ignored: false,
+            own_size: (meta.len(), allocated_size(meta)),
+            parent: None,
+        }
+    }
+
+    /// State for a subdirectory of this directory
+    #[allow(clippy::clone_on_copy)] // DirId is only Copy on unix
+    fn child(&'a self, ctx: &ScanContext, dir: &DirEntry) -> Frame<'a> {
+        let ignored = self.ignored || dir.ignored;
+        Frame {
+            id: dir.id.clone(),
+            rules: Self::rules_for(ctx, &dir.path, self.rules.hash, ignored),
+            ignored,
+            own_size: (dir.meta.len(), allocated_size(&dir.meta)),
+            parent: Some(self),
+        }
+    }
+
+    /// Load a directory's ignore rules if they can affect the scan
+    fn rules_for(ctx: &ScanContext, path: &Path, parent_hash: u64, ignored: bool) -> DirRules {
+        if ctx.options.respect_ignore_files && !ignored {
+            DirRules::load(path, parent_hash)
+        } else {
+            DirRules::inherited(parent_hash)
+        }
+    }
+
+    /// Whether a directory with this identity is already on the chain
+    fn contains(&self, id: Option<&DirId>) -> bool {
+        let Some(id) = id else { return false };
+        let mut current = Some(self);
+        while let Some(frame) = current {
+            if frame.id.as_ref() == Some(id) {
+                return true;
+            }
+            current = frame.parent;
+        }
+        false
+    }
+
+    /// Whether an entry of this directory is ignored; the innermost matching rule wins
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.ignored {
+            return true;
+        }
+        let mut current = Some(self);
+        while let Some(frame) = current {
+            if let Some(ignored) = frame.rules.matched(path, is_dir) {
+                return ignored;
+            }
+            current = frame.parent;
+        }
+        false
+    }
+}
+
+/// A filesystem error encountered while scanning, recorded instead of aborting the scan
+#[derive(Debug, Clone)]
+pub struct ScanError {
+    pub(crate) path: PathBuf,
+    pub(crate) kind: io::ErrorKind,
+    pub(crate) message: String,
+}
+
+impl ScanError {
+    fn new(path: &Path, err: &io::Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+
+    /// Get the path that could not be read
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the kind of the underlying I/O error
+    pub fn kind(&self) -> io::ErrorKind {
+        self.kind
+    }
+
+    /// Get the message of the underlying I/O error
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cannot access '{}': {}",
+            self.path.display(),
+            self.message
+        )
+    }
+}
+
+/// A file with more than one hard link, identified by device and inode
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HardLink {
+    pub(crate) dev: u64,
+    pub(crate) ino: u64,
+    pub(crate) size: u64,
+    pub(crate) allocated: u64,
+}
+
 /// Statistics for a directory and its contents
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DirStat {
     pub(crate) path: PathBuf,
-    pub(crate) total_size: u64, // Logical sum of st_size of all files
+    pub(crate) total_size: u64,     // Logical sum of st_size of all files
+    pub(crate) allocated_size: u64, // Sum of st_blocks * 512 of all files
     pub(crate) file_count: u64,
+    pub(crate) unique_size: u64, // Files with a single link
+    pub(crate) unique_allocated: u64,
+    pub(crate) shared_size: u64, // Hard-linked files, each inode counted once in this subtree
+    pub(crate) shared_allocated: u64,
+    pub(crate) hard_links: Vec<HardLink>, // Hard-linked files directly in this directory
+    pub(crate) symlink_count: u64, // Symlinks counted as links (not followed) in this subtree
+    pub(crate) dir_symlinks: u64,  // Symlinks directly in this directory, followed or not
+    pub(crate) followed_symlinks: bool, // Whether dir_symlinks were followed when scanned
+    pub(crate) ignored_size: u64,  // Files matched by ignore files, not in the totals above
+    pub(crate) ignored_allocated: u64,
+    pub(crate) ignored_file_count: u64,
+    pub(crate) ignored: bool, // This whole directory is matched by an ignore file
+    pub(crate) ignore_rules: u64, // Hash of the ignore rules in effect when scanned
+    pub(crate) own_size: u64, // The directory entry itself, not part of the totals
+    pub(crate) own_allocated: u64,
+    pub(crate) incomplete: bool, // Some entry in this subtree could not be read or was skipped
+    #[serde(skip)]
+    pub(crate) cancelled: bool, // The scan was cancelled before finishing this subtree
+    #[serde(skip)]
+    pub(crate) errors: Vec<ScanError>, // Errors for entries directly in this directory
     pub(crate) last_scan: SystemTime, // When this subtree was last scanned
+    pub(crate) mount_point: bool, // Skipped because it is on another filesystem
     pub(crate) children: HashMap<PathBuf, DirStat>,
 }
 
@@ -25,11 +496,167 @@ impl DirStat {
         self.total_size
     }
 
+    /// Get the allocated (on-disk) size of this directory
+    ///
+    /// Sparse files contribute only their allocated blocks, and small files
+    /// are rounded up to whole filesystem blocks, matching what `du` reports.
+    pub fn allocated_size(&self) -> u64 {
+        self.allocated_size
+    }
+
+    /// Get the size of this directory according to the given mode
+    pub fn size(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.total_size,
+            SizeMode::Allocated => self.allocated_size,
+        }
+    }
+
+    /// Get the size of files in this subtree that have a single link
+    pub fn unique_size(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.unique_size,
+            SizeMode::Allocated => self.unique_allocated,
+        }
+    }
+
+    /// Get the size of hard-linked files in this subtree, counting each inode once
+    pub fn shared_size(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.shared_size,
+            SizeMode::Allocated => self.shared_allocated,
+        }
+    }
+
+    /// Get the size of this directory with hard links deduplicated by (device, inode)
+    pub fn deduplicated_size(&self, mode: SizeMode) -> u64 {
+        self.unique_size(mode) + self.shared_size(mode)
+    }
+
     /// Get the file count in this directory
     pub fn file_count(&self) -> u64 {
         self.file_count
     }
 
+    /// Iterate over the immediate subdirectories, in no particular order
+    ///
+    /// Mount points skipped by `one_file_system` are included as empty entries.
+    pub fn children(&self) -> impl Iterator<Item = &DirStat> {
+        self.children.values()
+    }
+
+    /// Get the immediate subdirectories, largest first in the given size mode
+    ///
+    /// Directories of equal size are ordered by path.
+    pub fn children_by_size(&self, mode: SizeMode) -> Vec<&DirStat> {
+        let mut children: Vec<&DirStat> = self.children().collect();
+        children.sort_by(|a, b| {
+            b.size(mode)
+                .cmp(&a.size(mode))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        children
+    }
+
+    /// Get the immediate subdirectories ordered by path
+    pub fn children_by_name(&self) -> Vec<&DirStat> {
+        let mut children: Vec<&DirStat> = self.children().collect();
+        children.sort_by(|a, b| a.path.cmp(&b.path));
+        children
+    }
+
+    /// Iterate over this directory and every subdirectory, parents before children
+    pub fn iter_depth_first(&self) -> DepthFirst<'_> {
+        DepthFirst::new(self)
+    }
+
+    /// Iterate over this directory and every subdirectory, one level at a time
+    pub fn iter_breadth_first(&self) -> BreadthFirst<'_> {
+        BreadthFirst::new(self)
+    }
+
+    /// Find this directory or one of its subdirectories by path
+    ///
+    /// The path must be spelled like the scanned root, so when the root was
+    /// canonicalized (as `DiskUse` does), pass a canonical path too.
+    pub fn find(&self, path: impl AsRef<Path>) -> Option<&DirStat> {
+        let relative = path.as_ref().strip_prefix(&self.path).ok()?;
+        let mut current = self;
+        for component in relative.components() {
+            current = current.children.get(&current.path.join(component))?;
+        }
+        Some(current)
+    }
+
+    /// Get the number of directories in this subtree, including this one
+    ///
+    /// Mount points skipped by `one_file_system` are not counted.
+    pub fn dir_count(&self) -> u64 {
+        1 + self
+            .children
+            .values()
+            .filter(|child| !child.mount_point)
+            .map(DirStat::dir_count)
+            .sum::<u64>()
+    }
+
+    /// Get the number of symlinks counted as links rather than followed
+    pub fn symlink_count(&self) -> u64 {
+        self.symlink_count
+    }
+
+    /// Get the size of files matched by ignore files, which are left out of the totals
+    pub fn ignored_size(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.ignored_size,
+            SizeMode::Allocated => self.ignored_allocated,
+        }
+    }
+
+    /// Get the number of files matched by ignore files
+    pub fn ignored_file_count(&self) -> u64 {
+        self.ignored_file_count
+    }
+
+    /// Get the size of the directory entry itself, which is left out of the totals
+    ///
+    /// Tools like `du` count it; add it up over the subtree to match them.
+    pub fn own_size(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.own_size,
+            SizeMode::Allocated => self.own_allocated,
+        }
+    }
+
+    /// Whether this whole directory is matched by an ignore file
+    pub fn is_ignored(&self) -> bool {
+        self.ignored
+    }
+
+    /// Whether some entry in this subtree could not be read, leaving the totals short
+    ///
+    /// Incomplete directories are always rescanned rather than reused from the cache.
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    /// Whether the scan that produced this result was cancelled before finishing this subtree
+    ///
+    /// Directories that were not reached keep the figures of their previous
+    /// cached scan, or zero if there was none.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Get the errors encountered anywhere in this subtree during the scan that produced it
+    pub fn errors(&self) -> Vec<&ScanError> {
+        let mut errors: Vec<&ScanError> = self.errors.iter().collect();
+        for child in self.children.values() {
+            errors.extend(child.errors());
+        }
+        errors
+    }
+
     /// Get the last scan time
     pub fn last_scan(&self) -> SystemTime {
         self.last_scan
@@ -39,23 +666,237 @@ impl DirStat {
     pub fn path(&self) -> &Path {
         &self.path
     }
+
+    /// Whether this directory was skipped as a mount point of another filesystem
+    ///
+    /// Mount point entries carry no sizes or children of their own.
+    pub fn is_mount_point(&self) -> bool {
+        self.mount_point
+    }
+
+    /// Put a freshly scanned subdirectory into this tree in place of its cached entry
+    ///
+    /// The totals of every directory between this one and the subdirectory are
+    /// adjusted to match. Gives the subdirectory back if it is not in this
+    /// tree, for example because the scan that built the tree excluded it, or
+    /// was recorded here as a mount point.
+    pub(crate) fn graft(&mut self, stat: DirStat) -> std::result::Result<(), Box<DirStat>> {
+        self.graft_below(stat, false).map(|_| ())
+    }
+
+    /// Graft into this subtree, returning the totals of the replaced entry and its replacement
+    ///
+    /// With `add`, a subdirectory that is not in the tree yet is added to its
+    /// parent. When either entry has hard links, the hard-linked inodes of this
+    /// subtree are returned too, so each ancestor only collects those outside
+    /// the path to the graft.
+    fn graft_below(
+        &mut self,
+        stat: DirStat,
+        add: bool,
+    ) -> std::result::Result<(Additive, Additive, Option<LinkSet>), Box<DirStat>> {
+        let Some(first) = stat
+            .path
+            .strip_prefix(&self.path)
+            .ok()
+            .and_then(|relative| relative.components().next())
+        else {
+            return Err(Box::new(stat));
+        };
+        let key = self.path.join(first);
+        if self
+            .children
+            .get(&key)
+            .is_some_and(|child| child.mount_point)
+        {
+            return Err(Box::new(stat));
+        }
+
+        let (old, new, child_links) = if key == stat.path {
+            let old = self.children.get(&key).map(Additive::of);
+            if old.is_none() && !add {
+                return Err(Box::new(stat));
+            }
+            let new = Additive::of(&stat);
+            self.children.insert(key.clone(), stat);
+            (old.unwrap_or_default(), new, None)
+        } else {
+            match self.children.get_mut(&key) {
+                Some(child) => child.graft_below(stat, add)?,
+                None => return Err(Box::new(stat)),
+            }
+        };
+
+        self.total_size = self.total_size - old.total_size + new.total_size;
+        self.allocated_size = self.allocated_size - old.allocated_size + new.allocated_size;
+        self.file_count = self.file_count - old.file_count + new.file_count;
+        self.unique_size = self.unique_size - old.unique_size + new.unique_size;
+        self.unique_allocated = self.unique_allocated - old.unique_allocated + new.unique_allocated;
+        self.symlink_count = self.symlink_count - old.symlink_count + new.symlink_count;
+        self.ignored_size = self.ignored_size - old.ignored_size + new.ignored_size;
+        self.ignored_allocated =
+            self.ignored_allocated - old.ignored_allocated + new.ignored_allocated;
+        self.ignored_file_count =
+            self.ignored_file_count - old.ignored_file_count + new.ignored_file_count;
+        // Errors directly in this directory are not kept, so it stays incomplete until rescanned
+        self.incomplete |= new.incomplete;
+        self.cancelled |= new.cancelled;
+
+        // Hard links are deduplicated across the whole subtree, so recount them
+        let links = (old.has_links || new.has_links).then(|| {
+            // Below the graft's parent, the changed child's inodes come back from the level below
+            let from_below = child_links.is_some();
+            let mut links = child_links.unwrap_or_default();
+            for link in &self.hard_links {
+                links.insert((link.dev, link.ino), (link.size, link.allocated));
+            }
+            for (path, child) in &self.children {
+                if !from_below || *path != key {
+                    collect_links(child, &mut links);
+                }
+            }
+            (self.shared_size, self.shared_allocated) = links
+                .values()
+                .fold((0, 0), |(size, alloc), (s, a)| (size + s, alloc + a));
+            links
+        });
+
+        Ok((old, new, links))
+    }
+
+    /// Add a cached subtree below this directory, creating placeholders for
+    /// any directories in between that are not in this tree
+    ///
+    /// Gives the subtree back if it does not lie below this directory or lies
+    /// within a recorded mount point.
+    pub(crate) fn insert_descendant(
+        &mut self,
+        stat: DirStat,
+    ) -> std::result::Result<(), Box<DirStat>> {
+        let Some(parent) = stat
+            .path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(&self.path).ok())
+        else {
+            return Err(Box::new(stat));
+        };
+
+        let mut current = &mut *self;
+        for component in parent.components() {
+            let key = current.path.join(component);
+            current = current
+                .children
+                .entry(key)
+                .or_insert_with_key(|key| DirStat::placeholder(key));
+        }
+        self.graft_below(stat, true).map(|_| ())
+    }
+
+    /// Empty entry for a directory that has not been scanned
+    ///
+    /// It is marked incomplete, so a scan reads the directory instead of
+    /// reusing it, while still reusing any cached subtrees placed below it.
+    pub(crate) fn placeholder(path: &Path) -> Self {
+        DirStat {
+            incomplete: true,
+            mount_point: false,
+            ..DirStat::mount_point(path)
+        }
+    }
+
+    /// Placeholder recorded for a directory skipped by `one_file_system`
+    fn mount_point(path: &Path) -> Self {
+        DirStat {
+            path: path.to_path_buf(),
+            total_size: 0,
+            allocated_size: 0,
+            file_count: 0,
+            unique_size: 0,
+            unique_allocated: 0,
+            shared_size: 0,
+            shared_allocated: 0,
+            hard_links: Vec::new(),
+            symlink_count: 0,
+            dir_symlinks: 0,
+            followed_symlinks: false,
+            ignored_size: 0,
+            ignored_allocated: 0,
+            ignored_file_count: 0,
+            ignored: false,
+            ignore_rules: 0,
+            own_size: 0,
+            own_allocated: 0,
+            incomplete: false,
+            cancelled: false,
+            errors: Vec::new(),
+            last_scan: SystemTime::now(),
+            mount_point: true,
+            children: HashMap::new(),
+        }
+    }
+}
+
+/// Space allocated on disk for a file
+#[cfg(unix)]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.blocks() * 512
+}
+
+/// Space allocated on disk for a file (block counts are unavailable, so use length)
+#[cfg(not(unix))]
+fn allocated_size(meta: &fs::Metadata) -> u64 {
+    meta.len()
+}
+
+/// Device a file or directory resides on
+#[cfg(unix)]
+fn device_id(meta: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.dev())
+}
+
+/// Device a file or directory resides on (not available on this platform)
+#[cfg(not(unix))]
+fn device_id(_meta: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Identify a file that has other hard links
+#[cfg(unix)]
+fn hard_link(meta: &fs::Metadata) -> Option<HardLink> {
+    use std::os::unix::fs::MetadataExt;
+    (meta.nlink() > 1).then(|| HardLink {
+        dev: meta.dev(),
+        ino: meta.ino(),
+        size: meta.len(),
+        allocated: allocated_size(meta),
+    })
+}
+
+/// Identify a file that has other hard links (not tracked on this platform)
+#[cfg(not(unix))]
+fn hard_link(_meta: &fs::Metadata) -> Option<HardLink> {
+    None
 }
 
 /// Prune deleted directories from the cache recursively
 ///
 /// Removes any child DirStat entries whose paths no longer exist on disk.
 /// Returns true if any deletions were found and pruned.
-fn prune_deleted_dirs(cached: &mut DirStat) -> bool {
+fn prune_deleted_dirs(ctx: &ScanContext, cached: &mut DirStat) -> bool {
     let mut found_deletions = false;
 
     // Check direct children for deletions
     cached.children.retain(|child_path, child_stat| {
+        ctx.counters.stat();
         if !child_path.exists() {
             found_deletions = true;
+            ctx.counters.dirs_pruned(count_dirs(child_stat));
             false // Remove this entry
         } else {
             // Recursively prune this child's children
-            if prune_deleted_dirs(child_stat) {
+            if prune_deleted_dirs(ctx, child_stat) {
                 found_deletions = true;
             }
             true // Keep this entry
@@ -65,6 +906,11 @@ fn prune_deleted_dirs(cached: &mut DirStat) -> bool {
     found_deletions
 }
 
+/// Number of directories in a cached subtree, including its root
+fn count_dirs(stat: &DirStat) -> u64 {
+    1 + stat.children.values().map(count_dirs).sum::<u64>()
+}
+
 /// Check if a directory or any of its subdirectories have been modified
 ///
 /// Assumes deleted directories have already been pruned via prune_deleted_dirs.
@@ -72,49 +918,317 @@ fn prune_deleted_dirs(cached: &mut DirStat) -> bool {
 /// 1. Check if directory's own mtime > last_scan (files/dirs added/removed)
 /// 2. Check if any subdirectory's mtime > last_scan (changes within subdirs)
 /// 3. Recursively validate cached subdirectories
-fn dir_changed_since_last_scan(path: &Path, cached: &DirStat) -> bool {
-    // Check if the directory itself was modified
-    match fs::metadata(path).and_then(|m| m.modified()) {
-        Ok(mtime) => {
-            if mtime > cached.last_scan {
-                return true;
+/// 4. Check that cached mount point markers, symlink handling and ignore rules still match
+fn dir_changed_since_last_scan(
+    ctx: &ScanContext,
+    path: &Path,
+    cached: &DirStat,
+    frame: &Frame,
+) -> bool {
+    // Symlinks directly in this directory were resolved under a different policy
+    if cached.dir_symlinks > 0 && cached.followed_symlinks != ctx.follows_nested() {
+        return true;
+    }
+
+    // Ignore files here or in an ancestor were edited, or the directory's own status changed
+    if cached.ignore_rules != frame.rules.hash || cached.ignored != frame.ignored {
+        return true;
+    }
+
+    // Stop validating; the caller hands back the cached subtree as cancelled
+    if ctx.cancelled() {
+        return true;
+    }
+
+    // Read this directory under an I/O permit, released before recursing
+    let entries = {
+        let _permit = ctx.io_permit();
+
+        // Check if the directory itself was modified
+        ctx.counters.stat();
+        match fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(mtime) => {
+                if mtime > cached.last_scan {
+                    return true;
+                }
             }
+            Err(_) => return true,
         }
-        Err(_) => return true,
-    }
+
+        match fs::read_dir(path) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| ctx.classify(&entry, frame))
+                .collect::<Vec<_>>(),
+            Err(_) => return true,
+        }
+    };
 
     // Check if nested subdirectories are added that do not update mtime
-    match fs::read_dir(path) {
-        Ok(entries) => {
-            for entry in entries.flatten() {
-                let entry_path = entry.path();
-
-                if let Ok(meta) = entry.metadata() {
-                    if meta.is_dir() {
-                        // Check if this directory's mtime is newer than our last scan
-                        if let Ok(dir_mtime) = meta.modified() {
-                            if dir_mtime > cached.last_scan {
-                                return true;
-                            }
-                        }
-
-                        // Handle edge case that when nested subdirectories are added that do not update mtime
-                        // only for cached children as uncached children would be caught above by mtime check
-                        if let Some(child_cache) = cached.children.get(&entry_path) {
-                            if dir_changed_since_last_scan(&entry_path, child_cache) {
-                                return true;
-                            }
-                        }
+    for entry in entries {
+        match entry {
+            Ok(Entry::Dir(dir)) => {
+                // Check if this directory's mtime is newer than our last scan
+                if let Ok(dir_mtime) = dir.meta.modified() {
+                    if dir_mtime > cached.last_scan {
+                        return true;
                     }
                 }
+
+                // Handle edge case that when nested subdirectories are added that do not update mtime
+                // only for cached children as uncached children would be caught above by mtime check
+                if let Some(child_cache) = cached.children.get(&dir.path) {
+                    // A mount point skipped before but scanned now needs a rescan
+                    if child_cache.mount_point {
+                        return true;
+                    }
+                    let child_frame = frame.child(ctx, &dir);
+                    if dir_changed_since_last_scan(ctx, &dir.path, child_cache, &child_frame) {
+                        return true;
+                    }
+                }
+            }
+            // A directory scanned before but skipped now needs a rescan
+            Ok(Entry::MountPoint(entry_path))
+                if cached
+                    .children
+                    .get(&entry_path)
+                    .is_some_and(|child| !child.mount_point) =>
+            {
+                return true;
             }
+            // An entry that cannot be read now is reported by a rescan
+            Err(_) => return true,
+            _ => {}
         }
-        Err(_) => return true,
     }
 
     false
 }
 
+/// Totals of a subtree that add up into its parent's, used when grafting
+#[derive(Clone, Copy, Default)]
+struct Additive {
+    total_size: u64,
+    allocated_size: u64,
+    file_count: u64,
+    unique_size: u64,
+    unique_allocated: u64,
+    symlink_count: u64,
+    ignored_size: u64,
+    ignored_allocated: u64,
+    ignored_file_count: u64,
+    has_links: bool,
+    incomplete: bool,
+    cancelled: bool,
+}
+
+impl Additive {
+    fn of(stat: &DirStat) -> Self {
+        Self {
+            total_size: stat.total_size,
+            allocated_size: stat.allocated_size,
+            file_count: stat.file_count,
+            unique_size: stat.unique_size,
+            unique_allocated: stat.unique_allocated,
+            symlink_count: stat.symlink_count,
+            ignored_size: stat.ignored_size,
+            ignored_allocated: stat.ignored_allocated,
+            ignored_file_count: stat.ignored_file_count,
+            has_links: stat.shared_size > 0 || stat.shared_allocated > 0,
+            incomplete: stat.incomplete,
+            cancelled: stat.cancelled,
+        }
+    }
+}
+
+/// Hard-linked inodes seen in a subtree: (device, inode) -> (size, allocated)
+type LinkSet = HashMap<(u64, u64), (u64, u64)>;
+
+/// Running totals for a directory while its files and children are added
+#[derive(Default)]
+struct Totals {
+    total_size: u64,
+    allocated: u64,
+    file_count: u64,
+    unique_size: u64,
+    unique_allocated: u64,
+    hard_links: Vec<HardLink>,
+    symlink_count: u64,
+    dir_symlinks: u64,
+    ignored_size: u64,
+    ignored_allocated: u64,
+    ignored_file_count: u64,
+    incomplete: bool,
+    cancelled: bool,
+    errors: Vec<ScanError>,
+    links: LinkSet,
+    track_links: bool,
+}
+
+impl Totals {
+    /// Add a regular file located directly in this directory
+    fn add_file(&mut self, meta: &fs::Metadata) {
+        let size = meta.len();
+        let allocated = allocated_size(meta);
+        self.total_size += size;
+        self.allocated += allocated;
+        self.file_count += 1;
+
+        match hard_link(meta).filter(|_| self.track_links) {
+            Some(link) => {
+                self.links
+                    .insert((link.dev, link.ino), (link.size, link.allocated));
+                self.hard_links.push(link);
+            }
+            None => {
+                self.unique_size += size;
+                self.unique_allocated += allocated;
+            }
+        }
+    }
+
+    /// Add a symlink that is counted as the link itself
+    fn add_symlink(&mut self, meta: &fs::Metadata) {
+        let size = meta.len();
+        let allocated = allocated_size(meta);
+        self.total_size += size;
+        self.allocated += allocated;
+        self.unique_size += size;
+        self.unique_allocated += allocated;
+        self.symlink_count += 1;
+        self.dir_symlinks += 1;
+    }
+
+    /// Record an entry of this directory that could not be read
+    fn add_error(&mut self, path: &Path, err: &io::Error) {
+        self.errors.push(ScanError::new(path, err));
+    }
+
+    /// Add a directory entry that is not a subdirectory to descend into
+    fn add_entry(&mut self, entry: &Entry) {
+        match entry {
+            Entry::File(meta, via_link) => {
+                self.add_file(meta);
+                if *via_link {
+                    self.dir_symlinks += 1;
+                }
+            }
+            Entry::Symlink(meta) => self.add_symlink(meta),
+            Entry::Ignored(meta, is_link) => {
+                self.ignored_size += meta.len();
+                self.ignored_allocated += allocated_size(meta);
+                self.ignored_file_count += 1;
+                if *is_link {
+                    self.dir_symlinks += 1;
+                }
+            }
+            Entry::Dir(dir) if dir.via_link => self.dir_symlinks += 1,
+            Entry::Dir(_) | Entry::MountPoint(_) | Entry::Other => {}
+        }
+    }
+
+    /// Add a child directory together with the hard links found beneath it
+    fn add_child(&mut self, child: &DirStat, links: LinkSet) {
+        self.total_size += child.total_size;
+        self.allocated += child.allocated_size;
+        self.file_count += child.file_count;
+        self.unique_size += child.unique_size;
+        self.unique_allocated += child.unique_allocated;
+        self.symlink_count += child.symlink_count;
+        self.ignored_size += child.ignored_size;
+        self.ignored_allocated += child.ignored_allocated;
+        self.ignored_file_count += child.ignored_file_count;
+        self.incomplete |= child.incomplete;
+        self.cancelled |= child.cancelled;
+
+        // Merge the smaller set into the larger one to keep deep trees cheap
+        if links.len() > self.links.len() {
+            let smaller = std::mem::replace(&mut self.links, links);
+            self.links.extend(smaller);
+        } else {
+            self.links.extend(links);
+        }
+    }
+
+    /// Build the final stats, returning the subtree's hard links for the parent
+    fn finish(
+        self,
+        ctx: &ScanContext,
+        frame: &Frame,
+        path: &Path,
+        children: HashMap<PathBuf, DirStat>,
+    ) -> (DirStat, LinkSet) {
+        let (shared_size, shared_allocated) = self
+            .links
+            .values()
+            .fold((0, 0), |(size, alloc), (s, a)| (size + s, alloc + a));
+
+        let stat = DirStat {
+            path: path.to_path_buf(),
+            total_size: self.total_size,
+            allocated_size: self.allocated,
+            file_count: self.file_count,
+            unique_size: self.unique_size,
+            unique_allocated: self.unique_allocated,
+            shared_size,
+            shared_allocated,
+            hard_links: self.hard_links,
+            symlink_count: self.symlink_count,
+            dir_symlinks: self.dir_symlinks,
+            followed_symlinks: ctx.follows_nested(),
+            ignored_size: self.ignored_size,
+            ignored_allocated: self.ignored_allocated,
+            ignored_file_count: self.ignored_file_count,
+            ignored: frame.ignored,
+            ignore_rules: frame.rules.hash,
+            own_size: frame.own_size.0,
+            own_allocated: frame.own_size.1,
+            incomplete: self.incomplete || !self.errors.is_empty(),
+            cancelled: self.cancelled,
+            errors: self.errors,
+            last_scan: SystemTime::now(),
+            mount_point: false,
+            children,
+        };
+        (stat, self.links)
+    }
+}
+
+/// Collect the hard-linked inodes recorded anywhere in a cached subtree
+///
+/// Reused subtrees are not re-read from disk, so their inode sets have to be
+/// rebuilt from the cache for deduplication against the rest of the scan.
+fn collect_links(stat: &DirStat, links: &mut LinkSet) {
+    for link in &stat.hard_links {
+        links.insert((link.dev, link.ino), (link.size, link.allocated));
+    }
+    for child in stat.children.values() {
+        collect_links(child, links);
+    }
+}
+
+/// Stand-in for a directory that was not scanned because the scan was cancelled
+///
+/// Keeps the previously cached subtree, if any, so that its complete children
+/// are reused by the next scan; the directory itself is marked incomplete.
+fn cancelled_dir(
+    ctx: &ScanContext,
+    frame: &Frame,
+    path: &Path,
+    cache: Option<&DirStat>,
+) -> (DirStat, LinkSet) {
+    let mut stat = match cache {
+        Some(cached) => cached.clone(),
+        None => ctx.totals().finish(ctx, frame, path, HashMap::new()).0,
+    };
+    stat.incomplete = true;
+    stat.cancelled = true;
+
+    let links = ctx.links(&stat);
+    (stat, links)
+}
+
 /// Scan a directory recursively and return statistics
 ///
 /// # Arguments
@@ -123,120 +1237,256 @@ fn dir_changed_since_last_scan(path: &Path, cached: &DirStat) -> bool {
 ///
 /// # Returns
 /// Directory statistics including size, file count, and child directories
-pub fn scan_directory(path: &Path, cache: Option<&DirStat>) -> io::Result<DirStat> {
-    // If cache exists, first prune deleted directories, then check if rescan needed
-    if let Some(cached) = cache {
-        let mut pruned_cache = cached.clone();
-        let had_deletions = prune_deleted_dirs(&mut pruned_cache);
+#[cfg(test)]
+pub(crate) fn scan_directory(path: &Path, cache: Option<&DirStat>) -> Result<DirStat> {
+    scan_directory_with_options(path, cache, &ScanOptions::default())
+}
 
-        // If we found deletions, we need to recalculate totals from remaining children
-        if had_deletions {
-            // Recalculate total_size and file_count from remaining children
-            let mut total_size = 0;
-            let mut file_count = 0;
+/// Scan a directory recursively using the given options
+///
+/// See `scan_directory`; `options` controls how the tree is traversed. Only
+/// problems with `path` itself fail the scan: unreadable entries below it are
+/// recorded in the result (see `DirStat::errors`), and a cancelled scan returns
+/// the partial result (see `DirStat::is_cancelled`).
+pub fn scan_directory_with_options(
+    path: &Path,
+    cache: Option<&DirStat>,
+    options: &ScanOptions,
+) -> Result<DirStat> {
+    scan_directory_with_report(path, cache, options, None).map(|(stat, _)| stat)
+}
 
-            for child in pruned_cache.children.values() {
-                total_size += child.total_size;
-                file_count += child.file_count;
-            }
+/// Scan a directory recursively, reporting progress to `observer` after each directory
+///
+/// Also returns a report of how much work the scan did and how much it reused
+/// from the cache.
+pub fn scan_directory_with_report(
+    path: &Path,
+    cache: Option<&DirStat>,
+    options: &ScanOptions,
+    observer: Option<&dyn ProgressObserver>,
+) -> Result<(DirStat, ScanReport)> {
+    let started = Instant::now();
+    let filter = Filter::new(&options.exclude, &options.include)?;
+    let root_error = |source: io::Error| match source.kind() {
+        io::ErrorKind::NotFound => Error::RootNotFound(path.to_path_buf()),
+        _ => Error::Io {
+            path: path.to_path_buf(),
+            source,
+        },
+    };
+
+    // An unfollowed symlink root is reported as the link itself
+    let link_meta = fs::symlink_metadata(path).map_err(root_error)?;
+    if link_meta.file_type().is_symlink() && options.symlinks == SymlinkPolicy::Never {
+        let ctx = ScanContext {
+            options,
+            root: path,
+            root_dev: None,
+            filter,
+            io_limit: None,
+            deadline: None,
+            progress: None,
+            counters: ScanCounters::default(),
+        };
+        ctx.counters.stat();
+        let frame = Frame {
+            id: None,
+            rules: DirRules::inherited(0),
+            ignored: false,
+            own_size: (0, 0),
+            parent: None,
+        };
+        let mut totals = ctx.totals();
+        totals.add_symlink(&link_meta);
+        let (stat, _) = totals.finish(&ctx, &frame, path, HashMap::new());
+        return Ok((stat, ctx.counters.report(started.elapsed())));
+    }
+
+    let root_meta = fs::metadata(path).map_err(root_error)?;
+    if !root_meta.is_dir() {
+        return Err(Error::NotADirectory(path.to_path_buf()));
+    }
+    let parallelism = &options.parallelism;
+    let ctx = ScanContext {
+        options,
+        root: path,
+        root_dev: device_id(&root_meta),
+        filter,
+        io_limit: parallelism.max_concurrent_io.map(Semaphore::new),
+        deadline: options.time_budget.map(|budget| Instant::now() + budget),
+        progress: observer.map(ProgressTracker::new),
+        counters: ScanCounters::default(),
+    };
+    // The root's symlink_metadata and metadata calls
+    ctx.counters.stat();
+    ctx.counters.stat();
+    let frame = Frame::root(&ctx, path, &root_meta);
+    let scan = || {
+        scan_dir(&ctx, path, cache, &frame)
+            .map(|(stat, _)| (stat, ctx.counters.report(started.elapsed())))
+            .map_err(root_error)
+    };
+
+    // Run on a dedicated pool when the thread count is set, otherwise on the global one
+    match parallelism.threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(Error::ThreadPool)?
+            .install(scan),
+        None => scan(),
+    }
+}
+
+/// Recalculate a reused directory's totals after subdirectories were pruned from it
+///
+/// Files and links directly in the directory are counted from a fresh listing,
+/// and the remaining children are taken from the cache.
+fn recount_listing(ctx: &ScanContext, path: &Path, frame: &Frame, mut cached: DirStat) -> DirStat {
+    let mut totals = ctx.totals();
+
+    for child in cached.children.values() {
+        totals.add_child(child, ctx.links(child));
+    }
 
-            // Count files at this level (not in subdirs)
-            if let Ok(entries) = fs::read_dir(path) {
-                for entry in entries.flatten() {
-                    if let Ok(meta) = entry.metadata() {
-                        if meta.is_file() {
-                            total_size += meta.len();
-                            file_count += 1;
-                        }
+    // Count files and links at this level (not in subdirs)
+    let _permit = ctx.io_permit();
+    match fs::read_dir(path) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        totals.add_error(path, &err);
+                        continue;
                     }
+                };
+                match ctx.classify(&entry, frame) {
+                    Ok(classified) => totals.add_entry(&classified),
+                    Err(err) => totals.add_error(&entry.path(), &err),
                 }
             }
-
-            pruned_cache.total_size = total_size;
-            pruned_cache.file_count = file_count;
-            pruned_cache.last_scan = SystemTime::now();
         }
+        Err(err) => totals.add_error(path, &err),
+    }
+
+    let children = std::mem::take(&mut cached.children);
+    totals.finish(ctx, frame, path, children).0
+}
+
+/// Recursive worker for `scan_directory` that also returns the subtree's hard links
+fn scan_dir(
+    ctx: &ScanContext,
+    path: &Path,
+    cache: Option<&DirStat>,
+    frame: &Frame,
+) -> io::Result<(DirStat, LinkSet)> {
+    // A mount point marker has no contents to reuse once we descend into it, and
+    // an incomplete subtree must be read again (its complete children are still reused)
+    let cache = cache.filter(|cached| !cached.mount_point);
+    let reusable = cache.filter(|cached| !cached.incomplete);
+
+    // If cache exists, first prune deleted directories, then check if rescan needed
+    let mut pruned = None;
+    if let Some(cached) = reusable {
+        let mut pruned_cache = cached.clone();
+        let had_deletions = prune_deleted_dirs(ctx, &mut pruned_cache);
 
         // Now check if directory changed (excluding deletion checks)
-        if !dir_changed_since_last_scan(path, &pruned_cache) {
-            return Ok(pruned_cache);
+        if !dir_changed_since_last_scan(ctx, path, &pruned_cache, frame) {
+            // If we found deletions, we need to recalculate totals from remaining children
+            if had_deletions {
+                pruned_cache = recount_listing(ctx, path, frame, pruned_cache);
+            }
+            if !pruned_cache.incomplete {
+                if let Some(progress) = &ctx.progress {
+                    progress.subtree_reused(path, pruned_cache.file_count, pruned_cache.total_size);
+                }
+                // A directory whose listing was reread above counts as scanned, not reused
+                if had_deletions {
+                    ctx.counters.dir_scanned();
+                }
+                ctx.counters
+                    .dirs_reused(count_dirs(&pruned_cache) - u64::from(had_deletions));
+                let links = ctx.links(&pruned_cache);
+                return Ok((pruned_cache, links));
+            }
         }
+
+        // Subdirectories are scanned against the pruned tree, so deletions are found only once
+        pruned = Some(pruned_cache);
     }
+    let cache = pruned.as_ref().or(cache);
 
-    let mut total_size = 0;
-    let mut file_count = 0;
-    let mut children = HashMap::new();
+    if ctx.cancelled() {
+        return Ok(cancelled_dir(ctx, frame, path, cache));
+    }
 
-    // Collect entries first for potential parallel processing
-    let entries: Vec<_> = fs::read_dir(path)?.filter_map(|e| e.ok()).collect();
+    let mut totals = ctx.totals();
+    let mut children = HashMap::new();
 
     // Process files and collect subdirectories
     let mut subdirs = Vec::new();
 
-    for entry in entries {
-        let entry_path = entry.path();
-        if let Ok(meta) = entry.metadata() {
-            if meta.is_file() {
-                total_size += meta.len();
-                file_count += 1;
-            } else if meta.is_dir() {
-                subdirs.push(entry_path);
+    // The permit covers listing this directory, not scanning its children
+    let permit = ctx.io_permit();
+    for entry in fs::read_dir(path)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                totals.add_error(path, &err);
+                continue;
             }
-        }
-    }
-
-    // Process subdirectories in parallel if we have multiple
-    if subdirs.len() > 1 {
-        let results: Vec<_> = subdirs
-            .par_iter()
-            .filter_map(|entry_path| {
-                let child_cache = cache.and_then(|c| c.children.get(entry_path));
-                scan_directory(entry_path, child_cache).ok()
-            })
-            .collect();
-
-        for child_stat in results {
-            total_size += child_stat.total_size;
-            file_count += child_stat.file_count;
-            children.insert(child_stat.path.clone(), child_stat);
-        }
-    } else {
-        // Sequential processing for single subdirectory
-        for entry_path in subdirs {
-            let child_cache = cache.and_then(|c| c.children.get(&entry_path));
-            if let Ok(child_stat) = scan_directory(&entry_path, child_cache) {
-                total_size += child_stat.total_size;
-                file_count += child_stat.file_count;
-                children.insert(entry_path, child_stat);
+        };
+        let entry = match ctx.classify(&entry, frame) {
+            Ok(classified) => classified,
+            Err(err) => {
+                totals.add_error(&entry.path(), &err);
+                continue;
             }
+        };
+        totals.add_entry(&entry);
+        match entry {
+            Entry::Dir(dir) => subdirs.push(dir),
+            Entry::MountPoint(entry_path) => {
+                children.insert(entry_path.clone(), DirStat::mount_point(&entry_path));
+            }
+            _ => {}
         }
     }
+    drop(permit);
 
-    Ok(DirStat {
-        path: path.to_path_buf(),
-        total_size,
-        file_count,
-        last_scan: SystemTime::now(),
-        children,
-    })
-}
-
-/// Count files in a directory recursively (without using cache)
-pub fn count_files(path: &Path) -> io::Result<u64> {
-    let mut count = 0;
+    ctx.counters.dir_scanned();
+    if let Some(progress) = &ctx.progress {
+        progress.dir_scanned(path, totals.file_count, totals.total_size);
+    }
 
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
-        let meta = entry.metadata()?;
+    let scan_child = |dir: DirEntry| {
+        let child_cache = cache.and_then(|c| c.children.get(&dir.path));
+        let child_frame = frame.child(ctx, &dir);
+        scan_dir(ctx, &dir.path, child_cache, &child_frame).unwrap_or_else(|err| {
+            // An unreadable subdirectory is kept as an empty, incomplete node
+            let mut totals = ctx.totals();
+            totals.add_error(&dir.path, &err);
+            totals.finish(ctx, &child_frame, &dir.path, HashMap::new())
+        })
+    };
+
+    // Process subdirectories in parallel if there are enough of them
+    let results: Vec<_> = if subdirs.len() >= ctx.options.parallelism.parallel_threshold {
+        subdirs.into_par_iter().map(scan_child).collect()
+    } else {
+        // Sequential processing for few subdirectories
+        subdirs.into_iter().map(scan_child).collect()
+    };
 
-        if meta.is_file() {
-            count += 1;
-        } else if meta.is_dir() {
-            count += count_files(&entry.path())?;
-        }
+    for (child_stat, links) in results {
+        totals.add_child(&child_stat, links);
+        children.insert(child_stat.path.clone(), child_stat);
     }
 
-    Ok(count)
+    Ok(totals.finish(ctx, frame, path, children))
 }
 
 #[cfg(test)]
@@ -276,15 +1526,72 @@ mod tests {
     }
 
     #[test]
-    fn test_count_files() -> io::Result<()> {
+    fn test_children_and_dir_count() -> io::Result<()> {
         let temp_dir = TempDir::new()?;
         let test_dir = temp_dir.path().join("test");
         fs::create_dir(&test_dir)?;
+        create_test_structure(&test_dir)?;
+
+        let result = scan_directory(&test_dir, None)?;
+        assert_eq!(result.dir_count(), 4);
+
+        let mut children: Vec<_> = result
+            .children()
+            .map(|child| (child.path().to_path_buf(), child.file_count()))
+            .collect();
+        children.sort();
+        assert_eq!(
+            children,
+            vec![(test_dir.join("subdir1"), 1), (test_dir.join("subdir2"), 2)]
+        );
+
+        Ok(())
+    }
 
+    #[test]
+    fn test_tree_traversal() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
         create_test_structure(&test_dir)?;
 
-        let count = count_files(&test_dir)?;
-        assert_eq!(count, 5);
+        let result = scan_directory(&test_dir, None)?;
+        let nested = test_dir.join("subdir2/nested");
+
+        let depth_first: Vec<&Path> = result.iter_depth_first().map(DirStat::path).collect();
+        assert_eq!(depth_first.len(), 4);
+        assert_eq!(depth_first[0], test_dir);
+        let subdir2 = depth_first.iter().position(|p| p.ends_with("subdir2"));
+        let deep = depth_first.iter().position(|p| *p == nested);
+        assert!(subdir2 < deep);
+
+        let breadth_first: Vec<&Path> = result.iter_breadth_first().map(DirStat::path).collect();
+        assert_eq!(breadth_first.len(), 4);
+        assert_eq!(breadth_first[3], nested);
+
+        assert_eq!(result.find(&nested).unwrap().file_count(), 1);
+        assert_eq!(result.find(&test_dir).unwrap().file_count(), 5);
+        assert!(result.find(test_dir.join("missing")).is_none());
+        assert!(result.find(temp_dir.path()).is_none());
+
+        let by_size: Vec<&Path> = result
+            .children_by_size(SizeMode::Apparent)
+            .into_iter()
+            .map(DirStat::path)
+            .collect();
+        assert_eq!(
+            by_size,
+            vec![test_dir.join("subdir2"), test_dir.join("subdir1")]
+        );
+        let by_name: Vec<&Path> = result
+            .children_by_name()
+            .into_iter()
+            .map(DirStat::path)
+            .collect();
+        assert_eq!(
+            by_name,
+            vec![test_dir.join("subdir1"), test_dir.join("subdir2")]
+        );
 
         Ok(())
     }
@@ -387,6 +1694,509 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_deduplicated_across_cached_subtrees() -> io::Result<()> {
+        use std::thread::sleep;
+        use std::time::Duration;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir_all(test_dir.join("day1"))?;
+        fs::create_dir_all(test_dir.join("day2"))?;
+
+        // day2 is a `cp -al` style snapshot of day1 plus one new file
+        fs::write(test_dir.join("day1/model.bin"), "0123456789")?; // 10 bytes
+        fs::hard_link(
+            test_dir.join("day1/model.bin"),
+            test_dir.join("day2/model.bin"),
+        )?;
+        fs::write(test_dir.join("day2/extra.txt"), "abc")?; // 3 bytes
+
+        let options = ScanOptions {
+            dedup_hard_links: true,
+            ..Default::default()
+        };
+        let stats1 = scan_directory_with_options(&test_dir, None, &options)?;
+        assert_eq!(stats1.total_size(), 23);
+        assert_eq!(stats1.unique_size(SizeMode::Apparent), 3);
+        assert_eq!(stats1.shared_size(SizeMode::Apparent), 10);
+        assert_eq!(stats1.deduplicated_size(SizeMode::Apparent), 13);
+
+        let day2 = stats1.children.get(&test_dir.join("day2")).unwrap();
+        assert_eq!(day2.deduplicated_size(SizeMode::Apparent), 13);
+
+        sleep(Duration::from_millis(10));
+
+        // Add a third snapshot; day1 and day2 are reused from the cache
+        fs::create_dir(test_dir.join("day3"))?;
+        fs::hard_link(
+            test_dir.join("day1/model.bin"),
+            test_dir.join("day3/model.bin"),
+        )?;
+
+        let stats2 = scan_directory_with_options(&test_dir, Some(&stats1), &options)?;
+        let cached_day1 = stats2.children.get(&test_dir.join("day1")).unwrap();
+        assert_eq!(
+            cached_day1.last_scan(),
+            stats1.children[&test_dir.join("day1")].last_scan()
+        );
+        assert_eq!(stats2.total_size(), 33);
+        assert_eq!(stats2.deduplicated_size(SizeMode::Apparent), 13);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hard_links_not_tracked_without_dedup() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir_all(test_dir.join("day1"))?;
+        fs::create_dir_all(test_dir.join("day2"))?;
+        fs::write(test_dir.join("day1/model.bin"), "0123456789")?;
+        fs::hard_link(
+            test_dir.join("day1/model.bin"),
+            test_dir.join("day2/model.bin"),
+        )?;
+
+        let stats = scan_directory(&test_dir, None)?;
+        assert_eq!(stats.total_size(), 20);
+        assert_eq!(stats.shared_size(SizeMode::Apparent), 0);
+        assert_eq!(stats.deduplicated_size(SizeMode::Apparent), 20);
+        assert!(stats
+            .iter_depth_first()
+            .all(|dir| dir.hard_links.is_empty()));
+
+        let dedup = ScanOptions {
+            dedup_hard_links: true,
+            ..Default::default()
+        };
+        assert_ne!(dedup.fingerprint(), ScanOptions::default().fingerprint());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_mount_point_marker_is_rescanned() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_structure(&test_dir)?;
+
+        let options = ScanOptions {
+            one_file_system: true,
+            ..Default::default()
+        };
+
+        // Everything is on one device, so nothing is skipped
+        let stats1 = scan_directory_with_options(&test_dir, None, &options)?;
+        assert_eq!(stats1.file_count(), 5);
+        assert!(stats1.children.values().all(|c| !c.is_mount_point()));
+
+        // A cached marker that no longer matches the filesystem layout is not trusted
+        let mut stale = stats1.clone();
+        let subdir = test_dir.join("subdir1");
+        stale
+            .children
+            .insert(subdir.clone(), DirStat::mount_point(&subdir));
+        stale.file_count -= 1;
+
+        let stats2 = scan_directory_with_options(&test_dir, Some(&stale), &options)?;
+        assert_eq!(stats2.file_count(), 5);
+        assert!(!stats2.children[&subdir].is_mount_point());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policies() -> io::Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir_all(test_dir.join("data"))?;
+        fs::write(test_dir.join("data/file.txt"), "0123456789")?; // 10 bytes
+
+        symlink(test_dir.join("data"), test_dir.join("data_link"))?;
+        symlink(&test_dir, test_dir.join("data/loop"))?; // Cycle back to the root
+        symlink(test_dir.join("missing"), test_dir.join("broken"))?;
+
+        let link_size = |name: &str| fs::symlink_metadata(test_dir.join(name)).unwrap().len();
+        let links_total = link_size("data_link") + link_size("data/loop") + link_size("broken");
+
+        // Default (root only): links inside the tree are counted as links
+        let never = scan_directory(&test_dir, None)?;
+        assert_eq!(never.file_count(), 1);
+        assert_eq!(never.symlink_count(), 3);
+        assert_eq!(never.total_size(), 10 + links_total);
+
+        // Following: the linked directory is scanned again, the cycle and broken link are not
+        let options = ScanOptions {
+            symlinks: SymlinkPolicy::Always,
+            ..Default::default()
+        };
+        let always = scan_directory_with_options(&test_dir, Some(&never), &options)?;
+        assert_eq!(always.file_count(), 2);
+        assert_eq!(always.symlink_count(), 3); // loop inside data and data_link, broken
+        assert!(always.children.contains_key(&test_dir.join("data_link")));
+
+        // Switching back must not reuse the followed results from the cache
+        let again = scan_directory(&test_dir, Some(&always))?;
+        assert_eq!(again.file_count(), 1);
+        assert_eq!(again.total_size(), never.total_size());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exclude_and_include_patterns() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_structure(&test_dir)?;
+        fs::write(test_dir.join("subdir1/partial.tmp"), "scratch")?;
+
+        let options = ScanOptions {
+            exclude: vec!["*.tmp".to_string(), "subdir2/nested/".to_string()],
+            ..Default::default()
+        };
+        let stats = scan_directory_with_options(&test_dir, None, &options)?;
+        // 71 bytes minus deep.txt (17); the .tmp file is never counted
+        assert_eq!(stats.total_size(), 54);
+        assert_eq!(stats.file_count(), 4);
+        assert!(!stats.children[&test_dir.join("subdir2")]
+            .children
+            .contains_key(&test_dir.join("subdir2/nested")));
+
+        let options = ScanOptions {
+            include: vec!["file*.txt".to_string()],
+            ..Default::default()
+        };
+        let stats = scan_directory_with_options(&test_dir, None, &options)?;
+        assert_eq!(stats.total_size(), 23);
+        assert_eq!(stats.file_count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_files_tally_ignored_bytes() -> io::Result<()> {
+        use std::thread::sleep;
+        use std::time::Duration;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir_all(test_dir.join("src"))?;
+        fs::create_dir_all(test_dir.join("target/debug"))?;
+
+        fs::write(test_dir.join(".gitignore"), "target/\n")?; // 8 bytes
+        fs::write(test_dir.join("src/main.rs"), "fn main() {}")?; // 12 bytes
+        fs::write(test_dir.join("src/.ignore"), "*.bak\n")?; // 6 bytes
+        fs::write(test_dir.join("src/old.bak"), "backup")?; // 6 bytes
+        fs::write(test_dir.join("target/debug/app"), "0123456789")?; // 10 bytes
+
+        let options = ScanOptions {
+            respect_ignore_files: true,
+            ..Default::default()
+        };
+        let stats = scan_directory_with_options(&test_dir, None, &options)?;
+        assert_eq!(stats.total_size(), 26);
+        assert_eq!(stats.file_count(), 3);
+        assert_eq!(stats.ignored_size(SizeMode::Apparent), 16);
+        assert_eq!(stats.ignored_file_count(), 2);
+        assert!(stats.children[&test_dir.join("target")].is_ignored());
+
+        // Without the option nothing is ignored
+        let plain = scan_directory(&test_dir, None)?;
+        assert_eq!(plain.total_size(), 42);
+        assert_eq!(plain.ignored_file_count(), 0);
+
+        sleep(Duration::from_millis(10));
+
+        // Editing an ignore file in place invalidates the cached tree
+        fs::write(test_dir.join(".gitignore"), "target/\nsrc/\n")?;
+        let stats2 = scan_directory_with_options(&test_dir, Some(&stats), &options)?;
+        assert_eq!(stats2.total_size(), 13);
+        assert_eq!(stats2.ignored_size(SizeMode::Apparent), 34);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_subdirectory_is_reported() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_structure(&test_dir)?;
+
+        let locked = test_dir.join("subdir2");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000))?;
+        if fs::read_dir(&locked).is_ok() {
+            // Running with privileges that bypass permission checks
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+            return Ok(());
+        }
+
+        let stats = scan_directory(&test_dir, None)?;
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+
+        // The unreadable subtree is recorded but contributes nothing
+        assert_eq!(stats.total_size(), 42);
+        assert!(stats.is_incomplete());
+        assert!(stats.children[&locked].is_incomplete());
+        assert!(!stats.children[&test_dir.join("subdir1")].is_incomplete());
+
+        let errors = stats.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path(), locked);
+        assert_eq!(errors[0].kind(), io::ErrorKind::PermissionDenied);
+
+        // Once readable again, the cached incomplete node is not reused
+        let stats2 = scan_directory(&test_dir, Some(&stats))?;
+        assert_eq!(stats2.total_size(), 71);
+        assert!(!stats2.is_incomplete());
+        assert!(stats2.errors().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_incomplete_cache_is_rescanned() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_structure(&test_dir)?;
+
+        let mut stats = scan_directory(&test_dir, None)?;
+        let subdir1 = test_dir.join("subdir1");
+        let subdir2 = test_dir.join("subdir2");
+        stats.incomplete = true;
+        stats.children.get_mut(&subdir1).unwrap().incomplete = true;
+
+        let stats2 = scan_directory(&test_dir, Some(&stats))?;
+        assert!(!stats2.is_incomplete());
+        assert!(stats2.last_scan() > stats.last_scan());
+        assert!(stats2.children[&subdir1].last_scan() > stats.children[&subdir1].last_scan());
+        // Complete siblings are still reused
+        assert_eq!(
+            stats2.children[&subdir2].last_scan(),
+            stats.children[&subdir2].last_scan()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallelism_settings_do_not_change_results() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_structure(&test_dir)?;
+
+        let expected = scan_directory(&test_dir, None)?;
+        for parallelism in [
+            Parallelism {
+                threads: Some(1),
+                parallel_threshold: 1,
+                max_concurrent_io: Some(1),
+            },
+            Parallelism {
+                threads: Some(4),
+                parallel_threshold: usize::MAX,
+                max_concurrent_io: None,
+            },
+        ] {
+            let options = ScanOptions {
+                parallelism,
+                ..Default::default()
+            };
+            let stats = scan_directory_with_options(&test_dir, None, &options)?;
+            assert_eq!(stats.total_size(), expected.total_size());
+            assert_eq!(stats.file_count(), expected.file_count());
+
+            // Cache validation also runs under the I/O limit
+            let cached = scan_directory_with_options(&test_dir, Some(&stats), &options)?;
+            assert_eq!(cached.last_scan(), stats.last_scan());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancelled_scan_keeps_completed_subtrees() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_structure(&test_dir)?;
+
+        let token = CancelToken::new();
+        token.cancel();
+        let cancelled = ScanOptions {
+            cancel: Some(token),
+            ..Default::default()
+        };
+
+        // Cancelled before anything was read: nothing is counted
+        let empty = scan_directory_with_options(&test_dir, None, &cancelled)?;
+        assert!(empty.is_cancelled());
+        assert!(empty.is_incomplete());
+        assert_eq!(empty.total_size(), 0);
+
+        // With a cache, the unvalidated subtree is kept but not trusted
+        let full = scan_directory(&test_dir, None)?;
+        let partial = scan_directory_with_options(&test_dir, Some(&full), &cancelled)?;
+        assert!(partial.is_cancelled());
+        assert!(partial.is_incomplete());
+        assert_eq!(partial.total_size(), 71);
+
+        // The next scan rereads the root but reuses its complete children
+        let resumed = scan_directory(&test_dir, Some(&partial))?;
+        assert!(!resumed.is_cancelled());
+        assert!(!resumed.is_incomplete());
+        assert!(resumed.last_scan() > full.last_scan());
+        let subdir1 = test_dir.join("subdir1");
+        assert_eq!(
+            resumed.children[&subdir1].last_scan(),
+            full.children[&subdir1].last_scan()
+        );
+
+        // An exhausted time budget behaves like cancellation
+        let out_of_time = ScanOptions {
+            time_budget: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let stats = scan_directory_with_options(&test_dir, None, &out_of_time)?;
+        assert!(stats.is_cancelled());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress_reports_scanned_and_reused_directories() -> io::Result<()> {
+        use crate::progress::Progress;
+        use std::sync::Mutex;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_structure(&test_dir)?;
+
+        let latest = Mutex::new(Progress::default());
+        let observer = |progress: &Progress| {
+            let mut latest = latest.lock().unwrap();
+            if progress.dirs_visited + progress.cached_subtrees
+                >= latest.dirs_visited + latest.cached_subtrees
+            {
+                *latest = progress.clone();
+            }
+        };
+
+        let options = ScanOptions::default();
+        let (stats, _) = scan_directory_with_report(&test_dir, None, &options, Some(&observer))?;
+        {
+            let progress = latest.lock().unwrap();
+            assert_eq!(progress.dirs_visited, 4);
+            assert_eq!(progress.files_counted, 5);
+            assert_eq!(progress.bytes_counted, 71);
+            assert_eq!(progress.cached_subtrees, 0);
+        }
+
+        // An unchanged tree is reused as a whole
+        *latest.lock().unwrap() = Progress::default();
+        scan_directory_with_report(&test_dir, Some(&stats), &options, Some(&observer))?;
+        let progress = latest.lock().unwrap();
+        assert_eq!(progress.dirs_visited, 0);
+        assert_eq!(progress.cached_subtrees, 1);
+        assert_eq!(progress.bytes_counted, 71);
+        assert_eq!(progress.current_path, test_dir);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_report_counts_reused_scanned_and_pruned() -> io::Result<()> {
+        use std::thread::sleep;
+        use std::time::Duration;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir(&test_dir)?;
+        create_test_structure(&test_dir)?;
+        let options = ScanOptions::default();
+
+        let (cold, report) = scan_directory_with_report(&test_dir, None, &options, None)?;
+        assert_eq!(report.dirs_scanned, 4);
+        assert_eq!(report.dirs_reused, 0);
+        // Two for the root plus one per entry (5 files and 3 directories)
+        assert_eq!(report.stat_calls, 10);
+
+        let (_, report) = scan_directory_with_report(&test_dir, Some(&cold), &options, None)?;
+        assert_eq!(report.dirs_scanned, 0);
+        assert_eq!(report.dirs_reused, 4);
+
+        sleep(Duration::from_millis(10));
+        fs::remove_dir_all(test_dir.join("subdir2"))?;
+
+        // subdir2 and its nested directory are pruned; the root is rescanned
+        let (_, report) = scan_directory_with_report(&test_dir, Some(&cold), &options, None)?;
+        assert_eq!(report.dirs_pruned, 2);
+        assert_eq!(report.dirs_scanned, 1);
+        assert_eq!(report.dirs_reused, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_report_counts_each_directory_once() -> io::Result<()> {
+        use std::thread::sleep;
+        use std::time::Duration;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        fs::create_dir_all(test_dir.join("a/b/c/d"))?;
+        let options = ScanOptions::default();
+        let (cold, _) = scan_directory_with_report(&test_dir, None, &options, None)?;
+
+        sleep(Duration::from_millis(10));
+        fs::remove_dir(test_dir.join("a/b/c/d"))?;
+        fs::write(test_dir.join("a/touched.txt"), "a")?;
+        fs::write(test_dir.join("touched.txt"), "root")?;
+
+        // d is pruned once, not again at each rescanned ancestor, and each
+        // remaining directory is read once
+        let (stats, report) = scan_directory_with_report(&test_dir, Some(&cold), &options, None)?;
+        assert_eq!(stats.dir_count(), 4);
+        assert_eq!(report.dirs_pruned, 1);
+        assert_eq!(report.dirs_scanned + report.dirs_reused, 4);
+        assert_eq!(report.dirs_scanned, 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_errors() -> io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join("file.txt");
+        fs::write(&file, "content")?;
+
+        let missing = temp_dir.path().join("missing");
+        assert!(matches!(
+            scan_directory(&missing, None),
+            Err(Error::RootNotFound(path)) if path == missing
+        ));
+        assert!(matches!(
+            scan_directory(&file, None),
+            Err(Error::NotADirectory(path)) if path == file
+        ));
+
+        Ok(())
+    }
+
     #[test]
     fn test_prunes_deeply_nested_deleted_directory() -> io::Result<()> {
         use std::thread::sleep;
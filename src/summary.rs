@@ -0,0 +1,76 @@
+//! Totals of a scan gathered in a single walk of the tree
+
+use std::borrow::Cow;
+
+use crate::report::ScanReport;
+use crate::scanner::{DirStat, ScanError};
+
+/// Result of a scan: its totals together with the full directory tree
+///
+/// Everything is taken from the one walk that produced the tree, so reading
+/// the file or directory count never scans the filesystem again. The tree is
+/// borrowed from the cache it was stored in, and only owned when the scan
+/// bypassed the cache.
+#[derive(Debug, Clone)]
+pub struct ScanSummary<'a> {
+    size: u64,
+    file_count: u64,
+    dir_count: u64,
+    stats: Cow<'a, DirStat>,
+    report: &'a ScanReport,
+    errors: &'a [ScanError],
+}
+
+impl<'a> ScanSummary<'a> {
+    /// Summarize a scanned tree whose size was taken according to the scan options
+    pub(crate) fn new(
+        size: u64,
+        stats: Cow<'a, DirStat>,
+        report: &'a ScanReport,
+        errors: &'a [ScanError],
+    ) -> Self {
+        Self {
+            size,
+            file_count: stats.file_count(),
+            dir_count: stats.dir_count(),
+            stats,
+            report,
+            errors,
+        }
+    }
+
+    /// Get the total size in the scan's size mode, deduplicated if it was requested
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Get the number of files counted
+    pub fn file_count(&self) -> u64 {
+        self.file_count
+    }
+
+    /// Get the number of directories scanned, including the root
+    pub fn dir_count(&self) -> u64 {
+        self.dir_count
+    }
+
+    /// Get the scanned directory tree
+    pub fn stats(&self) -> &DirStat {
+        &self.stats
+    }
+
+    /// Get statistics about the work done by the scan, as `DiskUse::last_scan_report`
+    pub fn report(&self) -> &'a ScanReport {
+        self.report
+    }
+
+    /// Get the errors encountered by the scan, as `DiskUse::last_scan_errors`
+    pub fn errors(&self) -> &'a [ScanError] {
+        self.errors
+    }
+
+    /// Take the scanned directory tree, copying it if it is borrowed from the cache
+    pub fn into_stats(self) -> DirStat {
+        self.stats.into_owned()
+    }
+}
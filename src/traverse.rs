@@ -0,0 +1,55 @@
+//! Iterators walking a `DirStat` tree
+
+use std::collections::VecDeque;
+
+use crate::scanner::DirStat;
+
+/// Pre-order depth-first iterator over a directory and all its subdirectories
+///
+/// Created by `DirStat::iter_depth_first`. Each directory is yielded before
+/// its subdirectories, which are visited in no particular order.
+pub struct DepthFirst<'a> {
+    stack: Vec<&'a DirStat>,
+}
+
+impl<'a> DepthFirst<'a> {
+    pub(crate) fn new(root: &'a DirStat) -> Self {
+        Self { stack: vec![root] }
+    }
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = &'a DirStat;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stat = self.stack.pop()?;
+        self.stack.extend(stat.children());
+        Some(stat)
+    }
+}
+
+/// Breadth-first iterator over a directory and all its subdirectories
+///
+/// Created by `DirStat::iter_breadth_first`. Directories are yielded level by
+/// level, each level in no particular order.
+pub struct BreadthFirst<'a> {
+    queue: VecDeque<&'a DirStat>,
+}
+
+impl<'a> BreadthFirst<'a> {
+    pub(crate) fn new(root: &'a DirStat) -> Self {
+        Self {
+            queue: VecDeque::from([root]),
+        }
+    }
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = &'a DirStat;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stat = self.queue.pop_front()?;
+        self.queue.extend(stat.children());
+        Some(stat)
+    }
+}
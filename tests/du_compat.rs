@@ -0,0 +1,188 @@
+//! Compare `acme-disk-use du` with GNU `du` on generated trees
+//!
+//! Skipped when the `du` on the PATH is not GNU coreutils.
+
+#![cfg(unix)]
+
+use std::{
+    fs,
+    os::unix::fs::symlink,
+    path::Path,
+    process::{Command, Output},
+};
+
+use tempfile::TempDir;
+
+const FLAGS: &[&[&str]] = &[
+    &[],
+    &["-a"],
+    &["-s"],
+    &["-c"],
+    &["-h"],
+    &["-ah"],
+    &["-b"],
+    &["-ab"],
+    &["-k"],
+    &["-m"],
+    &["--apparent-size"],
+    &["--apparent-size", "-a"],
+    &["-d", "1"],
+    &["-d", "0", "-c"],
+    &["-a", "-d", "2", "-h"],
+    &["-x"],
+    &["-sh", "-c"],
+];
+
+/// Unit flags in different orders, where the last one wins as in `du`
+const UNIT_ORDERS: &[&[&str]] = &[
+    &["-h", "-k"],
+    &["-k", "-h"],
+    &["-hm"],
+    &["-mh"],
+    &["-b", "-m"],
+    &["-m", "-b"],
+    &["-k", "-b", "-h"],
+    &["-h", "-k", "-h"],
+    &["-a", "-m", "-k", "--apparent-size"],
+];
+
+fn gnu_du_available() -> bool {
+    Command::new("du")
+        .arg("--version")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains("GNU coreutils"))
+        .unwrap_or(false)
+}
+
+/// Build a tree with files of assorted sizes, an empty directory, an empty file and a symlink
+fn generate_tree(root: &Path) {
+    let sizes = [0, 1, 511, 4096, 4097, 12_345, 100_000, 1_500_000];
+    for (i, dir) in ["", "src", "src/deep/er", "docs", "empty", "data/set"]
+        .iter()
+        .enumerate()
+    {
+        let dir = root.join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        if i == 4 {
+            continue;
+        }
+        for (j, size) in sizes.iter().enumerate().skip(i % 3) {
+            fs::write(dir.join(format!("file{}.bin", j)), vec![b'x'; *size]).unwrap();
+        }
+    }
+    symlink("src/file1.bin", root.join("link")).unwrap();
+}
+
+/// Output of `du` and of `acme-disk-use du` run with the same arguments
+fn run_both(cwd: &Path, cache: &Path, args: &[&str]) -> (Output, Output) {
+    let expected = Command::new("du")
+        .args(args)
+        .current_dir(cwd)
+        .output()
+        .unwrap();
+    let actual = Command::new(env!("CARGO_BIN_EXE_acme-disk-use"))
+        .arg("du")
+        .args(args)
+        .current_dir(cwd)
+        .env("ACME_DISK_USE_CACHE", cache)
+        .output()
+        .unwrap();
+    (expected, actual)
+}
+
+fn lines(output: &Output) -> Vec<String> {
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout.clone())
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn test_output_matches_gnu_du() {
+    if !gnu_du_available() {
+        eprintln!("skipping: GNU du not found");
+        return;
+    }
+
+    let temp = TempDir::new().unwrap();
+    let tree = temp.path().join("tree");
+    generate_tree(&tree);
+    let cache = temp.path().join("cache");
+    let absolute = tree.to_str().unwrap();
+
+    let paths: &[&[&str]] = &[
+        &["tree"],
+        &["tree/"],
+        &["./tree/src"],
+        &[absolute],
+        &["tree/docs", "tree/link", "tree/src/file3.bin"],
+    ];
+
+    // The second round is answered from the cache written by the first
+    for round in 0..2 {
+        for flags in FLAGS.iter().chain(UNIT_ORDERS) {
+            for args in paths {
+                let (expected, actual) = run_both(temp.path(), &cache, &[flags, *args].concat());
+                assert_eq!(
+                    lines(&actual),
+                    lines(&expected),
+                    "round {} of du {:?} {:?}",
+                    round,
+                    flags,
+                    args
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_arguments_sharing_files_match_gnu_du() {
+    if !gnu_du_available() {
+        eprintln!("skipping: GNU du not found");
+        return;
+    }
+
+    // Two directories sharing a hard link, and a chain of single subdirectories
+    let temp = TempDir::new().unwrap();
+    let root = temp.path().join("root");
+    fs::create_dir_all(root.join("a")).unwrap();
+    fs::create_dir_all(root.join("b")).unwrap();
+    fs::write(root.join("a/big"), vec![b'x'; 100_000]).unwrap();
+    fs::hard_link(root.join("a/big"), root.join("b/big")).unwrap();
+    fs::create_dir_all(root.join("top/mid/leaf")).unwrap();
+    for (dir, size) in [("top", 5000), ("top/mid", 20_000), ("top/mid/leaf", 300)] {
+        fs::write(root.join(dir).join("file"), vec![b'x'; size]).unwrap();
+    }
+    let cache = temp.path().join("cache");
+
+    // Every case prints one directory per level, so the order is deterministic
+    let cases: &[&[&str]] = &[
+        &["a", "b"],
+        &["-c", "b", "a"],
+        &["-a", "a", "b"],
+        &["a/big", "b"],
+        &["b", "a/big", "b/big"],
+        &["-c", "top", "top/mid"],
+        &["-c", "top/mid", "top"],
+        &["-s", "top/mid/leaf", "top"],
+        &["top", "top"],
+        &["-c", "top", "top/mid/leaf/file"],
+        &["-c", "top/mid/leaf/file", "top/mid/leaf/file"],
+        &["-b", "-c", "top/mid", "top/./mid", "a", "b"],
+    ];
+    for round in 0..2 {
+        for args in cases {
+            let (expected, actual) = run_both(&root, &cache, args);
+            assert_eq!(
+                lines(&actual),
+                lines(&expected),
+                "round {} of du {:?}",
+                round,
+                args
+            );
+        }
+    }
+}